@@ -0,0 +1,121 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::UNIX_EPOCH;
+
+use alloy::primitives::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::repository::models::OwnershipTransfer;
+use crate::utils::did_document::{DocumentUpdate, DocumentUpdateOutcome};
+use crate::workers::maintenance::TaskStats;
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnerResponse {
+    pub contract: String,
+    pub owner: String,
+    pub signer: String,
+    pub signer_is_owner: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnershipTransferRequest {
+    pub new_owner: Address,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnershipAcceptRequest {
+    pub signature: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnershipTransferResponse {
+    pub id: String,
+    pub contract: String,
+    pub current_owner: String,
+    pub new_owner: String,
+    pub challenge: String,
+    pub status: String,
+    pub tx_hash: Option<String>,
+}
+
+impl From<OwnershipTransfer> for OwnershipTransferResponse {
+    fn from(value: OwnershipTransfer) -> Self {
+        Self {
+            id: value.id,
+            contract: value.contract,
+            current_owner: value.current_owner,
+            new_owner: value.new_owner,
+            challenge: value.challenge,
+            status: value.status,
+            tx_hash: value.tx_hash,
+        }
+    }
+}
+
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceTaskResponse {
+    pub task: String,
+    pub runs: u64,
+    pub failures: u64,
+    /// Unix time in seconds
+    pub last_run: Option<u64>,
+    pub last_duration_ms: u64,
+    pub last_removed: u64,
+    pub total_removed: u64,
+    pub last_error: Option<String>,
+}
+
+impl From<(&str, TaskStats)> for MaintenanceTaskResponse {
+    fn from((task, stats): (&str, TaskStats)) -> Self {
+        Self {
+            task: task.to_owned(),
+            runs: stats.runs,
+            failures: stats.failures,
+            last_run: stats.last_run
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|elapsed| elapsed.as_secs()),
+            last_duration_ms: stats.last_duration.as_millis() as u64,
+            last_removed: stats.last_removed,
+            total_removed: stats.total_removed,
+            last_error: stats.last_error,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocumentUpdateRequest {
+    #[serde(flatten)]
+    pub update: DocumentUpdate,
+    /// Publish the updated document, otherwise the diff is only previewed
+    #[serde(default)]
+    pub publish: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocumentUpdateResponse {
+    pub did: String,
+    pub diff: Vec<String>,
+    pub published: bool,
+    pub document: serde_json::Value,
+}
+
+impl From<DocumentUpdateOutcome> for DidDocumentUpdateResponse {
+    fn from(value: DocumentUpdateOutcome) -> Self {
+        Self {
+            did: value.document.id().to_string(),
+            document: serde_json::to_value(&value.document).unwrap_or_default(),
+            diff: value.diff,
+            published: value.published,
+        }
+    }
+}
\ No newline at end of file
@@ -3,9 +3,19 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::repository::models::ChallengePurpose;
 
 
 #[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ChallengeResponse {
     pub nonce: String,
+    pub purpose: ChallengePurpose,
+    /// Service that accepts the answer, i.e. the issuer URL
+    pub audience: String,
+    /// EIP-712 payload the wallet has to sign, absent with personal_sign
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub typed_data: Option<Value>,
 }
\ No newline at end of file
@@ -13,6 +13,9 @@ pub struct CredentialRequestDTO {
     pub nonce: String,
     pub identity_signature: String,
     pub wallet_signature: String,
+    /// Linked account being bound, CAIP-10 (`eip155:<chain id>:<address>`) or plain address.
+    /// When omitted every account linked in the DID document is tried
+    pub wallet_address: Option<String>,
     pub credential_subject: CredentialSubject
 }
 
@@ -37,4 +40,32 @@ pub struct CredentialIssuedResponse {
 #[serde(rename_all = "camelCase")]
 pub struct CredentialSubject {
     pub alternate_name: String
+}
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialStatusResponse {
+    pub credential_id: String,
+    pub registered: bool,
+    pub revoked: bool,
+    pub holder_address: Option<String>,
+    pub expiration: Option<String>,
+    pub registered_at_block: Option<i64>,
+    pub revoked_at_block: Option<i64>,
+    pub indexed_block: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuerDocumentResponse {
+    pub issuer: String,
+    pub did: String,
+    pub document: serde_json::Value,
+    /// RFC 3339 timestamps of the Alias Output metadata
+    pub created: Option<String>,
+    pub updated: Option<String>,
+    pub deactivated: bool,
+    pub state_controller_address: Option<String>,
+    pub governor_address: Option<String>,
+    /// did:web mirror of the document, served at /.well-known/did.json
+    pub did_web: Option<String>,
 }
\ No newline at end of file
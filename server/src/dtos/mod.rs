@@ -3,4 +3,6 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 pub mod identity_dtos;
-pub mod challenges_dtos;
\ No newline at end of file
+pub mod challenges_dtos;
+pub mod jobs_dtos;
+pub mod admin_dtos;
\ No newline at end of file
@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use deadpool_postgres::PoolError;
-use actix_web::{HttpResponse, ResponseError, http::header::ContentType};
+use actix_web::{HttpResponse, ResponseError, http::header::{ContentType, RETRY_AFTER}};
 use reqwest::StatusCode;
 
 #[derive(thiserror::Error, Debug)]
@@ -20,18 +20,31 @@ pub enum IssuerError {
     NonExistingRequestError,
     #[error("Invalid identity signature")]
     InvalidIdentitySignatureError,
+    #[error("Too many requests, retry in {0} seconds")]
+    RateLimitExceeded(u64),
+    #[error("Invalid DID: {0}")]
+    InvalidDid(String),
+    #[error("Unknown issuer: {0}")]
+    IssuerNotFound(String),
     
     // Iota Errors
+    // boxed, they would make every Result carrying an IssuerError large
     #[error("Identity Iota Error")]
-    IdentityIotaError(#[from] identity_iota::iota::Error),
+    IdentityIotaError(#[source] Box<identity_iota::iota::Error>),
     #[error("Iota Client Error")]
-    IotaClientError(#[from] iota_sdk::client::Error),
+    IotaClientError(#[source] Box<iota_sdk::client::Error>),
     #[error("Iota DID Error")]
     IotaDidError(#[from] identity_iota::did::Error),
+    #[error("DID document error: {0}")]
+    DidDocumentError(String),
     #[error("Verification method for ethereum address verification not found")]
     EthMethodNotFound,
-    #[error("Verification method type is not EcdsaSecp256k1RecoveryMethod2020")]
+    #[error("Verification method does not carry a valid Ethereum account")]
     InvalidVerificationMethodType,
+    #[error("Blockchain accounts not on the configured chain: {0}")]
+    WrongChainAccount(String),
+    #[error("Account {0} is not linked to the holder DID document")]
+    AccountNotLinked(String),
     // Smart Contracts Errors
     #[error("Public key recovery error")]
     SignatureError(#[from] alloy::primitives::SignatureError),
@@ -41,6 +54,8 @@ pub enum IssuerError {
     ContractError(String),
     #[error("Smart Contract address recovery Error")]
     ContractAddressRecoveryError,
+    #[error("Ownership error: {0}")]
+    OwnershipError(String),
     
     // Database Errors
     #[error("Row not found")]   
@@ -51,6 +66,18 @@ pub enum IssuerError {
     TokioPostgresMapperError(#[from] tokio_pg_mapper::Error),
     #[error("Pool error")]
     PoolError(#[from] PoolError),
+    #[error("SQLite error")]
+    SqliteError(#[from] rusqlite::Error),
+    #[error("SQLite pool error")]
+    SqlitePoolError(#[from] deadpool_sqlite::PoolError),
+    #[error("SQLite interaction error: {0}")]
+    SqliteInteractError(String),
+    #[error("Database configuration error: {0}")]
+    DatabaseConfigError(String),
+    #[error("Redis error")]
+    RedisError(#[from] redis::RedisError),
+    #[error("Challenge store error: {0}")]
+    ChallengeStoreError(String),
     
     //Identity Errors
     #[error("Verifiable Credential error, reason: {0}")]
@@ -65,10 +92,26 @@ pub enum IssuerError {
     Unknown,
 }
 
+impl From<identity_iota::iota::Error> for IssuerError {
+    fn from(err: identity_iota::iota::Error) -> Self {
+        IssuerError::IdentityIotaError(Box::new(err))
+    }
+}
+
+impl From<iota_sdk::client::Error> for IssuerError {
+    fn from(err: iota_sdk::client::Error) -> Self {
+        IssuerError::IotaClientError(Box::new(err))
+    }
+}
+
 impl ResponseError for IssuerError {
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
+        let mut response = HttpResponse::build(self.status_code());
+        if let IssuerError::RateLimitExceeded(retry_after) = self {
+            response.insert_header((RETRY_AFTER, retry_after.to_string()));
+        }
+        response
             .insert_header(ContentType::html())
             .body(self.to_string())
     }
@@ -80,21 +123,34 @@ impl ResponseError for IssuerError {
             IssuerError::InvalidOrPendingRequestError => StatusCode::BAD_REQUEST,
             IssuerError::NonExistingRequestError => StatusCode::NOT_FOUND,
             IssuerError::InvalidIdentitySignatureError => StatusCode::BAD_REQUEST,
+            IssuerError::RateLimitExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            IssuerError::InvalidDid(_) => StatusCode::BAD_REQUEST,
+            IssuerError::IssuerNotFound(_) => StatusCode::NOT_FOUND,
             IssuerError::IdentityIotaError(_) => StatusCode::INTERNAL_SERVER_ERROR,
                         IssuerError::IotaClientError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             IssuerError::IotaDidError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            IssuerError::DidDocumentError(_) => StatusCode::BAD_REQUEST,
             IssuerError::RowNotFound => StatusCode::NOT_FOUND,
             IssuerError::TokioPostgresError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             IssuerError::TokioPostgresMapperError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             IssuerError::PoolError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            IssuerError::SqliteError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            IssuerError::SqlitePoolError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            IssuerError::SqliteInteractError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            IssuerError::DatabaseConfigError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            IssuerError::RedisError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            IssuerError::ChallengeStoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             IssuerError::Unknown => StatusCode::INTERNAL_SERVER_ERROR,
             IssuerError::EthMethodNotFound => StatusCode::BAD_REQUEST,
             IssuerError::InvalidVerificationMethodType => StatusCode::BAD_REQUEST,
+            IssuerError::WrongChainAccount(_) => StatusCode::BAD_REQUEST,
+            IssuerError::AccountNotLinked(_) => StatusCode::BAD_REQUEST,
             IssuerError::SignatureError(_) => StatusCode::BAD_REQUEST,
             IssuerError::AddressRecoveryError => StatusCode::BAD_REQUEST,
             IssuerError::MiddlewareError(_) => StatusCode::UNAUTHORIZED,
             IssuerError::ContractAddressRecoveryError => StatusCode::INTERNAL_SERVER_ERROR,
             IssuerError::ContractError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            IssuerError::OwnershipError(_) => StatusCode::CONFLICT,
             IssuerError::OtherError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             IssuerError::CredentialNotFoundError(_) => StatusCode::UNAUTHORIZED,
                     }
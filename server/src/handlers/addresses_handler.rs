@@ -6,7 +6,7 @@ use actix_web::{get, web, HttpResponse, Responder};
 use identity_iota::core::ToJson;
 use serde::Serialize;
 
-use crate::{errors::IssuerError, utils::iota::{IotaState, SCAddresses}};
+use crate::{errors::IssuerError, utils::{iota::IotaState, issuers::CurrentIssuer}};
 
 #[derive(Serialize)]
 struct AddressResponse{
@@ -15,18 +15,17 @@ struct AddressResponse{
     fresc: String
 }
 
-impl From<&SCAddresses> for AddressResponse{
-    fn from(value: &SCAddresses) -> Self {
-        Self { identity: value.identity.to_string(), factory: value.factory.to_string(), fresc: value.fresc.to_string() }
-    }
-}
-
 /// Get SC Addresses managed by the issuer
 #[get("/addresses")]
-async fn get_addresses(iota_state: web::Data<IotaState>)
+async fn get_addresses(iota_state: web::Data<IotaState>, issuer: CurrentIssuer)
 -> Result<impl Responder, IssuerError>
 {
-    let addresses = Into::<AddressResponse>::into(&iota_state.addresses)
+    let addresses = AddressResponse {
+        identity: issuer.identity_sc.address().to_string(),
+        factory: iota_state.addresses.factory.to_string(),
+        fresc: iota_state.addresses.fresc.to_string(),
+    };
+    let addresses = addresses
         .to_json_value()
         .map_err(|_| IssuerError::OtherError("Address serialization failed".to_owned()))?;
     return Ok(HttpResponse::Ok().json(addresses))
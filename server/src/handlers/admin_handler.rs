@@ -0,0 +1,108 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web_lab::middleware::from_fn;
+use alloy::signers::k256::ecdsa::SigningKey;
+use alloy::signers::local::LocalSigner;
+
+use crate::dtos::admin_dtos::{
+    DidDocumentUpdateRequest, DidDocumentUpdateResponse, MaintenanceTaskResponse, OwnerResponse, OwnershipAcceptRequest, OwnershipTransferRequest, OwnershipTransferResponse
+};
+use crate::errors::IssuerError;
+use crate::middlewares::admin_auth::verify_admin_token;
+use crate::repository::database::Database;
+use crate::utils::configs::ConfirmationConfig;
+use crate::utils::did_document::update_issuer_document;
+use crate::utils::gas::GasStrategy;
+use crate::utils::iota::IotaState;
+use crate::utils::issuers::CurrentIssuer;
+use crate::utils::ownership::{accept_transfer, get_owner, propose_transfer};
+use crate::workers::maintenance::MaintenanceStats;
+
+/// Current owner of the Identity smart contract
+#[get("/owner")]
+async fn get_contract_owner(
+    issuer: CurrentIssuer,
+    signer: web::Data<LocalSigner<SigningKey>>,
+) -> Result<impl Responder, IssuerError> {
+    let owner = get_owner(&issuer.identity_sc).await?;
+    Ok(HttpResponse::Ok().json(OwnerResponse {
+        contract: issuer.identity_sc.address().to_string(),
+        owner: owner.to_string(),
+        signer: signer.address().to_string(),
+        signer_is_owner: owner == signer.address(),
+    }))
+}
+
+/// Propose the transfer of the Identity smart contract to a new issuer key.
+/// The response carries the challenge the new owner has to sign.
+#[post("/ownership-transfers")]
+async fn create_ownership_transfer(
+    req_body: web::Json<OwnershipTransferRequest>,
+    pool: web::Data<Database>,
+    issuer: CurrentIssuer,
+) -> Result<impl Responder, IssuerError> {
+    let db_client = pool.get().await?;
+    let transfer = propose_transfer(&db_client, &issuer.identity_sc, req_body.new_owner).await?;
+    Ok(HttpResponse::Created().json(OwnershipTransferResponse::from(transfer)))
+}
+
+/// Complete the latest proposed transfer with the signature of the new owner
+#[post("/ownership-transfers/accept")]
+async fn accept_ownership_transfer(
+    req_body: web::Json<OwnershipAcceptRequest>,
+    pool: web::Data<Database>,
+    issuer: CurrentIssuer,
+    gas_strategy: web::Data<GasStrategy>,
+    confirmation_config: web::Data<ConfirmationConfig>,
+) -> Result<impl Responder, IssuerError> {
+    let db_client = pool.get().await?;
+    let transfer = accept_transfer(
+        &db_client,
+        &issuer.identity_sc,
+        &req_body.signature,
+        &gas_strategy,
+        &confirmation_config,
+        Duration::from_secs(20),
+    ).await?;
+    Ok(HttpResponse::Ok().json(OwnershipTransferResponse::from(transfer)))
+}
+
+/// Add or remove services and set `alsoKnownAs` in the issuer DID document.
+/// The diff is returned without publishing unless `publish` is set.
+#[post("/did-document")]
+async fn update_did_document(
+    req_body: web::Json<DidDocumentUpdateRequest>,
+    iota_state: web::Data<IotaState>,
+    issuer: CurrentIssuer,
+) -> Result<impl Responder, IssuerError> {
+    let req_body = req_body.into_inner();
+    let outcome = update_issuer_document(&iota_state, &issuer, &req_body.update, req_body.publish).await?;
+    Ok(HttpResponse::Ok().json(DidDocumentUpdateResponse::from(outcome)))
+}
+
+/// Outcome of the maintenance tasks since the start of the issuer
+#[get("/maintenance")]
+async fn get_maintenance_stats(stats: web::Data<MaintenanceStats>) -> impl Responder {
+    let tasks: Vec<MaintenanceTaskResponse> = stats.snapshot()
+        .into_iter()
+        .map(MaintenanceTaskResponse::from)
+        .collect();
+    HttpResponse::Ok().json(tasks)
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .wrap(from_fn(verify_admin_token))
+            .service(get_contract_owner)
+            .service(create_ownership_transfer)
+            .service(accept_ownership_transfer)
+            .service(get_maintenance_stats)
+            .service(update_did_document)
+    );
+}
@@ -3,40 +3,67 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use actix_web::get;
-use actix_web::{web, HttpResponse, Responder};
-use deadpool_postgres::Pool;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use serde::Deserialize;
 use uuid::Uuid;
 
 use crate::dtos::challenges_dtos::ChallengeResponse;
 use crate::errors::IssuerError;
-use crate::repository::models::HolderChallenge;
+use crate::repository::challenge_store::ChallengeStore;
+use crate::repository::models::{ChallengePurpose, HolderChallenge};
 use crate::repository::operations::HoldersChallengesExt;
+use crate::utils::iota::IotaState;
+use crate::utils::issuers::CurrentIssuer;
+use crate::utils::rate_limit::ChallengeRateLimiters;
+use std::time::SystemTime;
+
 use identity_iota::core::{Timestamp, Duration};
+use identity_iota::iota::{IotaDID, IotaIdentityClientExt};
 
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Params {
     did: String,
+    /// Flow the challenge is meant for, issue when omitted
+    #[serde(default)]
+    purpose: ChallengePurpose,
 }
 
 /// Return a challenge that the client should sign and send back in a short time.
 /// Expiration allows to maintain a light db.
 /// It is expected that the holder calls the API for creating a credential within a minute.
-/// @param res --> 200, 400, 500
+/// Challenges are bound to a purpose (e.g. issue, revoke) and accepted only by that flow.
+/// A holder can have a single pending challenge per purpose, requests are rate limited per DID and per IP.
+/// @param res --> 200, 400, 429, 500
 #[get("/challenges")]
 async fn get_challenge(
+    req: HttpRequest,
     params: web::Query<Params>, 
-    db_pool: web::Data<Pool>,
+    challenge_store: web::Data<ChallengeStore>,
+    iota_state: web::Data<IotaState>,
+    rate_limiters: web::Data<ChallengeRateLimiters>,
+    issuer: CurrentIssuer,
 ) -> Result<impl Responder, IssuerError> {
     
     // let challenge = get_challenge_service(pool.get_ref().to_owned(), &params.did).await?;
     // Ok(HttpResponse::Ok().json(ChallengeResponse {nonce: challenge}))
 
     log::info!("get_challenge");
-    let pg_client = db_pool.get().await.map_err(IssuerError::PoolError)?;
-    log::info!("{}", params.did);
+    // forwarding headers are honoured only when the peer is a trusted proxy, otherwise they could be spoofed
+    let client_ip = rate_limiters.client_ip(
+        req.peer_addr().map(|addr| addr.ip()),
+        req.connection_info().realip_remote_addr(),
+    );
+    rate_limiters.per_ip.check(&client_ip)?;
+
+    let did = IotaDID::parse(&params.did).map_err(|err| IssuerError::InvalidDid(err.to_string()))?;
+    rate_limiters.per_did.check(&did.to_string())?;
+    log::info!("{}", did);
+
+    // only DIDs published on the ledger can later prove the challenge
+    iota_state.client.resolve_did(&did).await
+        .map_err(|err| IssuerError::InvalidDid(format!("{} cannot be resolved: {}", did, err)))?;
 
     // create nonce and store holder request (did, request expiration, nonce)
     let expiration = Timestamp::now_utc().checked_add(Duration::minutes(1)).unwrap();
@@ -44,15 +71,27 @@ async fn get_challenge(
     let nonce = Uuid::new_v4().to_string();
 
     let holder_challenge = HolderChallenge { 
-        did_holder: params.did.clone(), 
+        did_holder: did.to_string(), 
         challenge: nonce.clone(), 
-        expiration: expiration.to_rfc3339() 
+        expiration: SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(expiration.to_unix() as u64),
+        purpose: params.purpose.as_str().to_owned(),
+        audience: issuer.audience.clone(),
     };
 
     log::info!("Download request: {:?}", holder_challenge);
-    pg_client.insert_challenge(&holder_challenge).await?;
+    challenge_store.insert_challenge(&holder_challenge).await?;
     
-    Ok(HttpResponse::Ok().json(ChallengeResponse {nonce: nonce}))
+    // only the issuance binds a wallet
+    let typed_data = match params.purpose {
+        ChallengePurpose::Issue => issuer.wallet_binding.typed_data(&holder_challenge.did_holder, &nonce, expiration.to_unix()),
+        _ => None,
+    };
+    Ok(HttpResponse::Ok().json(ChallengeResponse {
+        nonce,
+        purpose: params.purpose,
+        audience: holder_challenge.audience,
+        typed_data
+    }))
 }
 
 // this function could be located in a different module
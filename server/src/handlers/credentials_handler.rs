@@ -3,63 +3,74 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use actix_web::{delete, post, web, HttpMessage, HttpRequest, HttpResponse, Responder};
-use alloy::primitives::{Address, U256};
-use alloy::providers::{DynProvider, Provider};
-use alloy::signers::k256::ecdsa::SigningKey;
-use alloy::signers::local::LocalSigner;
-use alloy::signers::Signature;
-use alloy::sol_types::SolEvent;
-use deadpool_postgres::Pool;
+use actix_web::http::header;
+use actix_web::{delete, get, post, web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use alloy::primitives::{Bytes, U256};
 
 use identity_eddsa_verifier::EdDSAJwsVerifier;
-use identity_iota::core::Timestamp;
-use identity_iota::credential::Jws;
+use identity_iota::credential::{DecodedJwtCredential, Jws, Jwt};
 use identity_iota::document::verifiable::JwsVerificationOptions;
-use identity_iota::iota::{IotaDID, IotaIdentityClientExt};
+use identity_iota::iota::{IotaDID, IotaDocument, IotaIdentityClientExt};
+use serde::Deserialize;
 use serde_json::json;
+use uuid::Uuid;
 
-use crate::contracts::Identity::{IdentityInstance, VC_Revoked};
-use crate::dtos::identity_dtos::{CredentialRequestDTO, CredentialIssuedResponse};
+use crate::dtos::identity_dtos::{CredentialRequestDTO, CredentialStatusResponse, CredentialSubject};
+use crate::dtos::jobs_dtos::IssuanceJobResponse;
 use crate::errors::IssuerError;
-use crate::repository::operations::HoldersChallengesExt;
-use crate::utils::configs::IssuerUrl;
-use crate::utils::eth::{update_identity_sc};
+use crate::repository::challenge_store::ChallengeStore;
+use crate::repository::database::Database;
+use crate::repository::models::{ChallengePurpose, IssuanceJob, JobStatus};
+use crate::repository::operations::{ContractEventsExt, HoldersChallengesExt, IssuanceJobsExt, VcIdReservationsExt};
+use crate::utils::configs::ConfirmationConfig;
+use crate::utils::blockchain_account::{holder_accounts, requested_account};
+use crate::utils::eth::TxState;
+use crate::utils::gas::GasStrategy;
 use crate::utils::iota::{create_credential, IotaState};
+use crate::utils::issuers::{CurrentIssuer, Issuer};
+use crate::utils::outbox::revoke_vc;
+use crate::workers::issuance_worker::IssuanceQueue;
 
 use actix_web_lab::middleware::from_fn;
 use crate::middlewares::ver_presentation_jwt::{verify_presentation_jwt, VerifiedPresentation};
 
+/// Path parameters by name, the routes served under `/api/{issuer}` hold two of them
+#[derive(Deserialize)]
+struct CredentialPath<T> {
+    credential_id: T,
+}
+
 #[post("/credentials")]
 async fn issue_credential (
   req_body: web::Json<CredentialRequestDTO>, 
-  pool: web::Data<Pool>,
+  pool: web::Data<Database>,
+  challenge_store: web::Data<ChallengeStore>,
   iota_state: web::Data<IotaState>,
-  identity_sc: web::Data<IdentityInstance<DynProvider>>,
-  issuer_url: web::Data<IssuerUrl>,
-  signer: web::Data<LocalSigner<SigningKey>>
+  issuer: CurrentIssuer,
+  issuance_queue: web::Data<IssuanceQueue>,
 ) -> Result<impl Responder, IssuerError> {
   log::info!("Issuing credential...");
 
   let credential_request = req_body.into_inner();
-  let pg_client = &pool.get().await?;
+  let db_client = &pool.get().await?;
   // read the request from the DB 
-  let holder_request = pg_client.get_challenge(&credential_request.did, &credential_request.nonce).await?;
+  // the challenge is consumed here, a failed request needs a new one.
+  // Challenges handed out by another issuer sharing the store have another audience and are not found
+  let holder_request = challenge_store.consume_challenge(&credential_request.did, &credential_request.nonce, ChallengePurpose::Issue, &issuer.audience).await?;
   log::info!("{:?}", holder_request);
 
-  //check challenge expiration
-  let expiration = Timestamp::from_str(&holder_request.expiration)
-      .map_err(|_| {IssuerError::OtherError("Unsupported timestamp format".to_owned())})?;
-
   // guard the code returning early if the challenge is expired
-  if Timestamp::now_utc() > expiration {
+  if SystemTime::now() > holder_request.expiration {
       return Err(IssuerError::ChallengeExpired)
   }
+  let expiration = holder_request.expiration.duration_since(UNIX_EPOCH)
+      .map_err(|_| IssuerError::OtherError("Invalid challenge expiration".to_owned()))?
+      .as_secs() as i64;
 
   // resolve DID Doc and extract public key
-  let holder_document = iota_state.client.resolve_did(&IotaDID::parse(holder_request.did_holder)?).await?;
+  let holder_document = iota_state.client.resolve_did(&IotaDID::parse(&holder_request.did_holder)?).await?;
   
   // Verify DID ownership, i.e. challenge signed equal to the stored nonce (anti replay)
   let _decoded_jws =  holder_document.verify_jws(
@@ -69,88 +80,153 @@ async fn issue_credential (
       &JwsVerificationOptions::default().nonce(&holder_request.challenge),
   )?;
   
-  let credential_id: U256 = identity_sc
-    .getFreeVCid()
-    .call()
-    .await
-    .map_err(|err| IssuerError::ContractError(format!("VC ID request failed: {}",err.to_string())))?;
-
-  let credential_id_url = issuer_url.join(format!("api/credentials/{}",&credential_id.to_string()).as_str())
-    .map_err(|_|IssuerError::OtherError("Parsing error".to_owned()))?;
-
-  // Create and sign the credential
-  let (credential_jwt, decoded_jwt_credential) = create_credential(
-    &holder_document,
-    &iota_state.issuer_document, 
-    credential_id_url, 
-    &iota_state.key_storage,
-    &iota_state.issuer_identity.fragment,
-    credential_request.credential_subject
-  ).await.map_err(|e| IssuerError::OtherError(format!("Conversion error: {}", e.to_string())))?;
-
   // Verify the EOA ownership
   log::info!("Wallet sign: {:?}", credential_request.wallet_signature);
-  let wallet_sign = Signature::from_str(credential_request.wallet_signature.as_str())?;
-  log::info!("signature {:?}", wallet_sign);
-  let vm = holder_document.resolve_method("#ethAddress", None).ok_or(IssuerError::EthMethodNotFound)?;
+  let wallet_sign = Bytes::from_str(credential_request.wallet_signature.as_str())
+    .map_err(|_| IssuerError::OtherError("Invalid wallet signature encoding".to_owned()))?;
+  // Ethereum accounts listed by the holder on the configured chain (CAIP-10 or secp256k1 JWK)
+  let wallet_binding = &issuer.wallet_binding;
+  let mut accounts = holder_accounts(&holder_document, wallet_binding.chain_id)?;
+  log::info!("eth accounts: {:?}", accounts);
+  if let Some(requested) = &credential_request.wallet_address {
+    let requested = requested_account(requested, wallet_binding.chain_id)?;
+    if !accounts.contains(&requested) {
+      return Err(IssuerError::AccountNotLinked(requested.to_string()));
+    }
+    accounts = vec![requested];
+  }
 
-  vm.type_().to_string().eq("EcdsaSecp256k1RecoveryMethod2020").then(|| Some(())).ok_or(IssuerError::InvalidVerificationMethodType)?;
-  
-  let eth_addr = vm.data().custom()
-  .take_if(|method_data| {method_data.name == "blockchainAccountId"} )
-  .ok_or(IssuerError::InvalidVerificationMethodType)?
-  .data.as_str()
-  .ok_or(IssuerError::InvalidVerificationMethodType)?
-  .strip_prefix("eip155:1:")
-  .ok_or(IssuerError::InvalidVerificationMethodType)?;
-
-  log::info!("eth addr: {}", eth_addr);
-  let address: Address = eth_addr.parse().map_err(|_| IssuerError::AddressRecoveryError)?;
-
-  let recovered_address = wallet_sign.recover_address_from_msg(holder_request.challenge.clone())?;
-  if address.ne(&recovered_address){
+  // The holder signs the EIP-712 binding of its DID to the challenge (or the raw challenge with personal_sign)
+  let mut binding = None;
+  for address in accounts {
+    binding = wallet_binding.verify(
+      issuer.identity_sc.provider(),
+      address,
+      &wallet_sign,
+      &holder_request.did_holder,
+      &holder_request.challenge,
+      expiration
+    ).await?;
+    if binding.is_some() {
+      break;
+    }
+  }
+  let Some(binding) = binding else {
     return Ok(HttpResponse::Unauthorized().json(json!({"error": "Signature verification failed"})))
+  };
+  if binding.contract_wallet {
+    log::info!("Wallet {} is a smart contract, addUser receives its ERC-1271 signature", binding.address);
   }
   log::info!("Wallet signature verification success!");
   
-  let provider = identity_sc.provider();
-  let nonce = provider.get_transaction_count(signer.address()).await
-    .inspect(|nonce|  log::info!("Next NONCE: {}",nonce))
-    .map_err(|_| IssuerError::OtherError("Cannot read the nonce from the provider".to_owned()))?;
-
-  // Update Identity SC, addUser 
-  update_identity_sc(
-      identity_sc, 
-      decoded_jwt_credential,
-      credential_id, 
-      holder_request.challenge,
-      &credential_request.wallet_signature, 
-      nonce
-  ).await
-  .inspect_err(|err| log::error!("{}", err))?;
-
-  pg_client.remove_challenge(&holder_document.id().to_string()).await?;
-
-  let response = CredentialIssuedResponse { 
-      message: "Verifiable Credential issued".to_owned(),
-      issuer_did: iota_state.get_ref().issuer_identity.did.clone(),
-      credential_id: credential_id,
-      credential_jwt: credential_jwt
+  // Reserve the VC id before signing, so concurrent requests never share it
+  let job_id = Uuid::new_v4().to_string();
+  let first_free_id: U256 = issuer.identity_sc
+    .getFreeVCid()
+    .call()
+    .await
+    .map_err(|err| IssuerError::ContractError(format!("VC ID request failed: {}", err)))?;
+  let first_free_id = i64::try_from(first_free_id)
+    .map_err(|_| IssuerError::OtherError("VC ID out of range".to_owned()))?;
+  let reservation = db_client.reserve_vc_id(&issuer.name, first_free_id, &job_id).await?;
+  let credential_id = U256::from(reservation.vc_id);
+  log::info!("Reserved VC id {} for job {}", credential_id, job_id);
+
+  let job = async {
+    let (credential_jwt, decoded_jwt_credential) = sign_credential(
+      &iota_state,
+      &issuer,
+      &holder_document,
+      credential_id,
+      credential_request.credential_subject
+    ).await?;
+    let expiration_date = decoded_jwt_credential.credential.expiration_date
+      .ok_or(IssuerError::OtherError("Expiration date not found".to_owned()))?
+      .to_unix();
+    let now = SystemTime::now();
+
+    // The on-chain registration (addUser) is carried out by the issuance worker
+    let job = IssuanceJob {
+        id: job_id.clone(),
+        did_holder: holder_document.id().to_string(),
+        credential_id: credential_id.to_string(),
+        credential_jwt: credential_jwt.as_str().to_owned(),
+        wallet_signature: credential_request.wallet_signature,
+        wallet_address: binding.address.to_string(),
+        challenge: binding.contract_challenge,
+        signature_scheme: wallet_binding.scheme.as_str().to_owned(),
+        issuance_date: decoded_jwt_credential.credential.issuance_date.to_unix(),
+        expiration_date,
+        status: JobStatus::Pending.as_str().to_owned(),
+        tx_hash: None,
+        error: None,
+        created_at: now,
+        updated_at: now,
+        issuer: issuer.name.clone(),
+    };
+    db_client.insert_issuance_job(&job).await
+  }.await;
+
+  let job = match job {
+    Ok(job) => job,
+    Err(err) => {
+      // the id can be handed out again
+      db_client.release_vc_id(&job_id).await?;
+      return Err(err);
+    }
   };
-  Ok(HttpResponse::Ok().json(response))
+  issuance_queue.wake();
+
+  let response = IssuanceJobResponse {
+      job_id: job.id.clone(),
+      status: job.status,
+      issuer_did: issuer.identity.did.clone(),
+      credential_id: job.credential_id,
+      wallet_address: job.wallet_address,
+      credential_jwt: None,
+      tx_hash: None,
+      error: None,
+  };
+  Ok(HttpResponse::Accepted()
+    .insert_header((header::LOCATION, format!("{}issuance-jobs/{}", issuer.api_url.path(), job.id)))
+    .json(response))
+}
+
+/// Create and sign the credential with the reserved id, following the template of the issuer
+async fn sign_credential(
+  iota_state: &IotaState,
+  issuer: &Issuer,
+  holder_document: &IotaDocument,
+  credential_id: U256,
+  credential_subject: CredentialSubject,
+) -> Result<(Jwt, DecodedJwtCredential), IssuerError> {
+  let credential_id_url = issuer.api_url.join(format!("credentials/{}",&credential_id.to_string()).as_str())
+    .map_err(|_|IssuerError::OtherError("Parsing error".to_owned()))?;
+
+  create_credential(
+    holder_document,
+    &issuer.document(),
+    credential_id_url, 
+    &iota_state.key_storage,
+    &issuer.identity.fragment,
+    &issuer.template,
+    credential_subject
+  ).await.map_err(|e| IssuerError::OtherError(format!("Conversion error: {}", e)))
 }
 
 #[delete("/credentials/{credential_id}", wrap = "from_fn(verify_presentation_jwt)")]
 async fn revoke_credential (
     req: HttpRequest,
-    path: web::Path<i64>,
-    identity_sc: web::Data<IdentityInstance<DynProvider>>,
-    signer: web::Data<LocalSigner<SigningKey>>
+    path: web::Path<CredentialPath<i64>>,
+    pool: web::Data<Database>,
+    issuer: CurrentIssuer,
+    gas_strategy: web::Data<GasStrategy>,
+    confirmation_config: web::Data<ConfirmationConfig>
 ) -> Result<impl Responder, IssuerError> {
 
     log::info!("Revoking credential...");
     // Ensure that the VC verified is exactly the one requested from the user
-    let credential_id = path.into_inner();
+    let credential_id = path.into_inner().credential_id;
     let verfied_data = req.extensions().get::<VerifiedPresentation>()
         .ok_or(IssuerError::MiddlewareError("Middleware result not found".to_owned())).cloned()?;
     
@@ -158,36 +234,63 @@ async fn revoke_credential (
         return Err(IssuerError::CredentialNotFoundError("Credential ID does not match with the requested one"));
     }
     
-    let provider = identity_sc.provider();
-    let nonce = provider.get_transaction_count(signer.address()).await
-        .inspect(|nonce|  log::info!("Next NONCE: {}",nonce))
-        .map_err(|_| IssuerError::OtherError("Cannot read the nonce from the provider".to_owned()))?;
-    let receipt = identity_sc.revokeVC(U256::from(credential_id))
-        .gas_price(10_000_000_000)
-        .nonce(nonce)
-        .send()
-        .await
-        .map_err(|err| IssuerError::ContractError(err.to_string()))?
-        .with_timeout(Some(Duration::from_secs(20)))
-        .get_receipt()
-        .await
-        .map_err(|err| IssuerError::ContractError(err.to_string()))?;
-
-    // reading the log   
-    for log in receipt.logs() {
-        // finding the event
-        if let Ok(event) =  <VC_Revoked as SolEvent>::decode_log(&log.inner){
-            log::info!("VcRevoked event for vc id {:?}", event.vc_id);
-            return Ok(HttpResponse::Ok().finish());
+    let db_client = pool.get().await?;
+    match revoke_vc(&db_client, &issuer, credential_id, &gas_strategy, confirmation_config.confirmation_depth, Duration::from_secs(20)).await? {
+        TxState::Confirmed => Ok(HttpResponse::Ok().finish()),
+        // the reconciler will follow the transaction
+        TxState::Pending | TxState::Dropped => Ok(HttpResponse::Accepted().json(json!({"message": "Revocation submitted, waiting for confirmation"}))),
+        TxState::Failed(reason) => Err(IssuerError::ContractError(reason)),
+    }
+}
+
+
+/// Return the on-chain status of a credential as seen by the event indexer
+/// @param res --> 200, 500
+#[get("/credentials/{credential_id}/status")]
+async fn get_credential_status (
+    path: web::Path<CredentialPath<u64>>,
+    pool: web::Data<Database>,
+    issuer: CurrentIssuer,
+) -> Result<impl Responder, IssuerError> {
+    let credential_id = path.into_inner().credential_id.to_string();
+    let contract = issuer.identity_sc.address().to_string();
+    let db_client = pool.get().await?;
+
+    let mut response = CredentialStatusResponse {
+        credential_id: credential_id.clone(),
+        ..Default::default()
+    };
+    response.indexed_block = match db_client.get_indexer_cursor(&contract).await {
+        Ok(cursor) => Some(cursor.block_number),
+        Err(IssuerError::RowNotFound) => None,
+        Err(err) => return Err(err),
+    };
+
+    for event in db_client.get_events_by_vc_id(&contract, &credential_id).await? {
+        match event.event.as_str() {
+            "VC_added" => {
+                let data: serde_json::Value = serde_json::from_str(&event.data)
+                    .map_err(|_| IssuerError::OtherError("Invalid event data".to_owned()))?;
+                response.registered = true;
+                response.holder_address = event.account;
+                response.expiration = data["expiration"].as_str().map(str::to_owned);
+                response.registered_at_block = Some(event.block_number);
+            }
+            "VC_Revoked" => {
+                response.revoked = true;
+                response.revoked_at_block = Some(event.block_number);
+            }
+            _ => {}
         }
     }
-    Err(IssuerError::OtherError("no VcRevoked event found in the receipt".to_owned()))
+    Ok(HttpResponse::Ok().json(response))
 }
 
 
 pub fn scoped_config(cfg: &mut web::ServiceConfig) {
     cfg
     .service(issue_credential)
-    .service(revoke_credential);
+    .service(revoke_credential)
+    .service(get_credential_status);
 
 }
\ No newline at end of file
@@ -0,0 +1,41 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::dtos::identity_dtos::IssuerDocumentResponse;
+use crate::errors::IssuerError;
+use crate::utils::did_web::DidWeb;
+use crate::utils::issuers::{CurrentIssuer, IssuerRegistry};
+
+/// Last published DID document of the issuer, with the metadata of its Alias Output
+/// @param res --> 200, 404, 500
+#[get("/did-document")]
+async fn get_did_document(
+    issuer: CurrentIssuer,
+    issuers: web::Data<IssuerRegistry>,
+    did_web: Option<web::Data<DidWeb>>,
+) -> Result<impl Responder, IssuerError> {
+    let document = issuer.document();
+    let document_json = serde_json::to_value(document.core_document())
+        .map_err(|err| IssuerError::OtherError(err.to_string()))?;
+    let metadata = &document.metadata;
+    Ok(HttpResponse::Ok().json(IssuerDocumentResponse {
+        issuer: issuer.name.clone(),
+        did: document.id().to_string(),
+        document: document_json,
+        created: metadata.created.map(|created| created.to_rfc3339()),
+        updated: metadata.updated.map(|updated| updated.to_rfc3339()),
+        deactivated: metadata.deactivated.unwrap_or(false),
+        state_controller_address: metadata.state_controller_address.clone(),
+        governor_address: metadata.governor_address.clone(),
+        did_web: did_web
+            .filter(|_| issuers.is_default(&issuer))
+            .map(|did_web| did_web.did().to_string()),
+    }))
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_did_document);
+}
\ No newline at end of file
@@ -0,0 +1,59 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::str::FromStr;
+
+use actix_web::{get, web, HttpResponse, Responder};
+use identity_iota::credential::Jwt;
+use serde::Deserialize;
+
+use crate::dtos::jobs_dtos::IssuanceJobResponse;
+use crate::errors::IssuerError;
+use crate::repository::database::Database;
+use crate::repository::models::JobStatus;
+use crate::repository::operations::IssuanceJobsExt;
+use crate::utils::issuers::CurrentIssuer;
+
+#[derive(Deserialize)]
+struct JobPath {
+    job_id: String,
+}
+
+/// Return the status of an issuance job.
+/// The credential JWT is released only once the VC_added event is confirmed.
+/// @param res --> 200, 404, 500
+#[get("/issuance-jobs/{job_id}")]
+async fn get_issuance_job(
+    path: web::Path<JobPath>,
+    pool: web::Data<Database>,
+    issuer: CurrentIssuer,
+) -> Result<impl Responder, IssuerError> {
+    let job_id = path.into_inner().job_id;
+    let db_client = pool.get().await?;
+    let job = db_client.get_issuance_job(&job_id).await?;
+    // jobs of the other issuers are not visible
+    if job.issuer != issuer.name {
+        return Err(IssuerError::RowNotFound);
+    }
+
+    let status = JobStatus::from_str(&job.status).map_err(IssuerError::OtherError)?;
+    let credential_jwt = (status == JobStatus::Confirmed).then(|| Jwt::from(job.credential_jwt));
+
+    let response = IssuanceJobResponse {
+        job_id: job.id,
+        status: job.status,
+        issuer_did: issuer.identity.did.clone(),
+        credential_id: job.credential_id,
+        wallet_address: job.wallet_address,
+        credential_jwt,
+        tx_hash: job.tx_hash,
+        error: job.error,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg
+    .service(get_issuance_job);
+}
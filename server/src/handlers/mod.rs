@@ -4,4 +4,20 @@
 
 pub mod credentials_handler;
 pub mod challenges_handler;
-pub mod addresses_handler;
\ No newline at end of file
+pub mod addresses_handler;
+pub mod issuance_jobs_handler;
+pub mod admin_handler;
+pub mod well_known_handler;
+pub mod did_document_handler;
+
+use actix_web::web;
+
+/// Routes of an issuer api, served under `/api` for the default issuer and under `/api/{issuer}` for every issuer
+pub fn api_config(cfg: &mut web::ServiceConfig) {
+    cfg.configure(credentials_handler::scoped_config)
+        .configure(challenges_handler::scoped_config)
+        .configure(addresses_handler::scoped_config)
+        .configure(issuance_jobs_handler::scoped_config)
+        .configure(did_document_handler::scoped_config)
+        .configure(admin_handler::scoped_config);
+}
\ No newline at end of file
@@ -0,0 +1,46 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::errors::IssuerError;
+use crate::utils::did_web::DidWeb;
+use crate::utils::domain_linkage::DomainLinkage;
+use crate::utils::iota::IotaState;
+use crate::utils::issuers::IssuerRegistry;
+
+/// DID Configuration resource linking the issuer DIDs to the origin of the issuer url
+/// @param res --> 200, 404, 500
+#[get("/did-configuration.json")]
+async fn get_did_configuration(
+    domain_linkage: Option<web::Data<DomainLinkage>>,
+    iota_state: web::Data<IotaState>,
+    issuers: web::Data<IssuerRegistry>,
+) -> Result<impl Responder, IssuerError> {
+    let domain_linkage = domain_linkage.ok_or(IssuerError::RowNotFound)?;
+    let configuration = domain_linkage.configuration(&iota_state, &issuers).await?;
+    Ok(HttpResponse::Ok().json(configuration))
+}
+
+/// did:web document of the issuer host, mirroring the keys of the default issuer
+/// @param res --> 200, 404, 500
+#[get("/did.json")]
+async fn get_did_web_document(
+    did_web: Option<web::Data<DidWeb>>,
+    issuers: web::Data<IssuerRegistry>,
+) -> Result<impl Responder, IssuerError> {
+    let did_web = did_web.ok_or(IssuerError::RowNotFound)?;
+    let document = did_web.mirror(&issuers.resolve(None)?.document())?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/did+json")
+        .json(document))
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/.well-known")
+            .service(get_did_configuration)
+            .service(get_did_web_document)
+    );
+}
\ No newline at end of file
@@ -8,4 +8,5 @@ pub mod utils;
 pub mod errors;
 pub mod repository;
 pub mod middlewares;
-pub mod contracts;
\ No newline at end of file
+pub mod contracts;
+pub mod workers;
\ No newline at end of file
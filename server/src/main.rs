@@ -6,26 +6,41 @@ use std::ops::Deref;
 use std::time::Duration;
 
 use actix_cors::Cors;
-use actix_web::{http, middleware::Logger, web, App, HttpServer};
+use actix_web::{guard, http, middleware::Logger, web, App, HttpServer};
 use alloy::network::Ethereum;
 use alloy::primitives::U256;
 use alloy::providers::{DynProvider, Provider, ProviderBuilder};
 use alloy::signers::k256::ecdsa::SigningKey;
 use alloy::signers::local::{LocalSigner, PrivateKeySigner};
-use alloy::sol_types::SolEvent;
-use deadpool_postgres::Pool;
 #[cfg(debug_assertions)]
 use dotenv::dotenv;
-use lib_issuer::contracts::Identity::{IdentityInstance, VC_Revoked};
+use lib_issuer::contracts::Identity::IdentityInstance;
 use lib_issuer::contracts::{Identity};
 use lib_issuer::errors::IssuerError;
-use lib_issuer::handlers::{addresses_handler, challenges_handler, credentials_handler};
-use lib_issuer::repository::postgres_repo::init;
+use lib_issuer::handlers::{api_config, well_known_handler};
+use lib_issuer::middlewares::admin_auth::AdminToken;
+use lib_issuer::repository::challenge_store::ChallengeStore;
+use lib_issuer::repository::database::Database;
 use lib_issuer::utils::configs::{
-    Commands, DLTConfig, DatabaseConfig, HttpServerConfig, IssuerConfig, KeyStorageConfig
+    ChallengeStoreConfig, Commands, ConfirmationConfig, DLTConfig, DatabaseConfig, DocumentCommandArgs, HttpServerConfig, IssuerConfig, KeyStorageConfig
 };
 
+use lib_issuer::utils::deployment::{deploy_identity, ContractsState};
+use lib_issuer::utils::diagnostics::{run_diagnostics, DiagnosticsTarget};
+use lib_issuer::utils::did_web::DidWeb;
+use lib_issuer::utils::did_document::{update_issuer_document, DocumentUpdate, ServiceEntry, ServiceKind};
+use lib_issuer::utils::domain_linkage::DomainLinkage;
+use lib_issuer::utils::gas::GasStrategy;
 use lib_issuer::utils::iota::IotaState;
+use lib_issuer::utils::issuers::{Issuer, IssuerChain, IssuerDefinition, IssuerRegistry};
+use lib_issuer::utils::eth::TxState;
+use lib_issuer::utils::outbox;
+use lib_issuer::utils::rate_limit::{ChallengeRateLimiters, RateLimiter};
+use lib_issuer::utils::ownership::{accept_transfer, ensure_signer_is_owner, get_owner, propose_transfer};
+use lib_issuer::workers::event_indexer::event_indexer;
+use lib_issuer::workers::issuance_worker::{issuance_worker, IssuanceQueue};
+use lib_issuer::workers::maintenance::{maintenance_scheduler, MaintenanceStats};
+use lib_issuer::workers::reconciler::reconciler;
 
 use clap::Parser;
 
@@ -53,6 +68,10 @@ struct Args {
     #[command(flatten)]
     database_config: DatabaseConfig,
 
+    /// Challenge store configuration
+    #[command(flatten)]
+    challenge_store_config: ChallengeStoreConfig,
+
     #[command(subcommand)]
     commands: Option<Commands>
 }
@@ -68,7 +87,12 @@ async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     // Initialize database connection pool
-    let db_pool = init(args.database_config).await?;
+    let db_pool = Database::init(args.database_config).await?;
+    // The schema is always brought up to date, the migrate command stops here
+    db_pool.migrate().await?;
+    if matches!(args.commands, Some(Commands::Migrate)) {
+        return Ok(());
+    }
 
     // Initialize provider
     let rpc_provider = &args.dlt_config.rpc_provider;
@@ -80,32 +104,246 @@ async fn main() -> anyhow::Result<()> {
         .connect_http(rpc_provider.deref().clone());
     provider.client().set_poll_interval(Duration::from_millis(500));
     let provider = DynProvider::<Ethereum>::new(provider);
-    let identity_address = args.dlt_config.identity_sc_address;
-    let identity_sc = Identity::new(identity_address, provider);
+    let gas_strategy = GasStrategy::new(&args.dlt_config.gas_config, args.dlt_config.chain_id);
+    log::info!("Gas strategy: {:?}", gas_strategy);
+    let mut indexer_config = args.dlt_config.indexer_config.clone();
+    let confirmation_config = args.dlt_config.confirmation_config;
+
+    let state_file = args.dlt_config.contracts_state_file.clone();
+    if let Some(Commands::DeployContracts { force }) = args.commands {
+        return deploy_contracts(&provider, &args.dlt_config, &gas_strategy, force).await;
+    }
 
-    // Initialize iota_state (client, did, etc.), create or load issuer's identity.
-    let iota_state = IotaState::init(&db_pool, args.dlt_config, args.key_storage_config).await?;
+    // Contracts deployed by the issuer take precedence over the configured address
+    let contracts_state = ContractsState::load(&state_file)?
+        .filter(|state| {
+            let same_chain = state.chain_id == args.dlt_config.chain_id;
+            if !same_chain {
+                log::warn!("Ignoring {}: recorded for chain {}", state_file.display(), state.chain_id);
+            }
+            same_chain
+        });
+    let identity_address = match (&contracts_state, args.dlt_config.identity_sc_address) {
+        (Some(state), configured) => {
+            if configured.is_some_and(|configured| configured != state.identity_sc_address) {
+                log::warn!("IDENTITY_SC_ADDRESS overridden by {}", state_file.display());
+            }
+            if indexer_config.indexer_start_block == 0 {
+                indexer_config.indexer_start_block = state.deployment_block;
+            }
+            state.identity_sc_address
+        },
+        (None, Some(configured)) => configured,
+        (None, None) => anyhow::bail!("IDENTITY_SC_ADDRESS not set and no contracts deployed, run deploy-contracts first"),
+    };
+    // The configured contract belongs to the first (default) issuer
+    let definitions = IssuerDefinition::load(args.issuer_config.issuers_file.as_deref())?;
+    let contracts = IssuerDefinition::resolve_contracts(&definitions, identity_address)?;
+    let identity_contracts: Vec<(String, IdentityInstance<DynProvider>)> = contracts.iter()
+        .map(|(name, address)| (name.clone(), Identity::new(*address, provider.clone())))
+        .collect();
+
+    match &args.commands {
+        // Refuse to serve when RPC, chain or smart contracts do not match the configuration
+        None | Some(Commands::Diagnostics) => {
+            let report = run_diagnostics(DiagnosticsTarget {
+                provider: &provider,
+                identity_contracts: &identity_contracts,
+                factory: args.dlt_config.factory_sc_address,
+                fresc: args.dlt_config.fresc_sc_address,
+                chain_id: args.dlt_config.chain_id,
+                signer: signer.address(),
+                min_signer_balance: U256::from(args.dlt_config.min_signer_balance),
+                signature_scheme: args.dlt_config.wallet_signature_scheme,
+            }).await;
+            if matches!(args.commands, Some(Commands::Diagnostics)) {
+                println!("{}", report);
+            } else {
+                report.log();
+            }
+            if !report.is_healthy() {
+                anyhow::bail!("startup self-check failed, see the report above");
+            }
+            if args.commands.is_some() {
+                return Ok(());
+            }
+        },
+        Some(Commands::Owner { .. })
+        | Some(Commands::AddService { .. })
+        | Some(Commands::RemoveService { .. })
+        | Some(Commands::SetAlsoKnownAs { .. })
+        | Some(Commands::LinkDomain { .. })
+        | Some(Commands::DeployContracts { .. })
+        | Some(Commands::Migrate) => {},
+        // Fail fast when the issuer signer cannot write to the Identity contract of the selected issuer
+        Some(Commands::Revoke { issuer, .. })
+        | Some(Commands::TransferOwnership { issuer, .. })
+        | Some(Commands::AcceptOwnership { issuer, .. }) => {
+            let identity_sc = identity_contracts.iter()
+                .find(|(name, _)| issuer.as_ref().is_none_or(|issuer| issuer == name))
+                .map(|(_, identity_sc)| identity_sc)
+                .ok_or(IssuerError::IssuerNotFound(issuer.clone().unwrap_or_default()))?;
+            ensure_signer_is_owner(identity_sc, signer.address()).await?
+        },
+    }
+
+    // Initialize iota_state (client, stronghold), then create or load the identity of every issuer.
+    let chain = IssuerChain {
+        provider: provider.clone(),
+        chain_id: args.dlt_config.chain_id,
+        signature_scheme: args.dlt_config.wallet_signature_scheme,
+    };
+    let iota_state = IotaState::init(args.dlt_config, args.key_storage_config).await?;
+    let issuers = web::Data::new(IssuerRegistry::init(
+        &db_pool,
+        &iota_state,
+        definitions,
+        contracts.clone(),
+        &chain,
+        &args.issuer_config.issuer_url,
+    ).await?);
     let iota_state_data = web::Data::new(iota_state);
     
     match args.commands {
         None => 
             {
-                let identity_sc= web::Data::new(identity_sc);
-                start_server(db_pool, identity_sc, iota_state_data, args.issuer_config, signer, args.http_server_config).await
+                // every issuer contract has its own cursor
+                for (_, address) in contracts {
+                    tokio::task::spawn(event_indexer(db_pool.clone(), provider.clone(), address, indexer_config.clone()));
+                }
+                let challenge_store = web::Data::new(ChallengeStore::new(&args.challenge_store_config, db_pool.clone()).await?);
+                let transactions = TransactionSettings { signer, gas_strategy, confirmation_config };
+                start_server(db_pool, challenge_store, issuers, iota_state_data, args.issuer_config, transactions, args.http_server_config).await
             },
-        Some(Commands::Revoke { credential }) => revoke_credential(identity_sc, credential).await,
+        Some(Commands::Diagnostics) | Some(Commands::Migrate) | Some(Commands::DeployContracts { .. }) => Ok(()),
+        Some(Commands::Revoke { credential, issuer }) => {
+            let issuer = issuers.resolve(issuer.as_deref())?;
+            revoke_credential(db_pool, &issuer, &gas_strategy, confirmation_config, credential).await
+        },
+        Some(Commands::Owner { issuer }) => {
+            let issuer = issuers.resolve(issuer.as_deref())?;
+            let owner = get_owner(&issuer.identity_sc).await?;
+            println!("Issuer: {} ({})", issuer.name, issuer.identity.did);
+            println!("Identity contract: {}", issuer.identity_sc.address());
+            println!("Owner: {}", owner);
+            println!("Issuer signer: {} (owner: {})", signer.address(), owner == signer.address());
+            Ok(())
+        },
+        Some(Commands::TransferOwnership { new_owner, issuer }) => {
+            let issuer = issuers.resolve(issuer.as_deref())?;
+            let db_client = db_pool.get().await?;
+            let transfer = propose_transfer(&db_client, &issuer.identity_sc, new_owner).await?;
+            println!("Ownership transfer {} proposed to {}", transfer.id, transfer.new_owner);
+            println!("The new owner must sign (personal_sign) the following message and run accept-ownership:");
+            println!("{}", transfer.challenge);
+            Ok(())
+        },
+        Some(Commands::AcceptOwnership { signature, issuer }) => {
+            let issuer = issuers.resolve(issuer.as_deref())?;
+            let db_client = db_pool.get().await?;
+            let transfer = accept_transfer(&db_client, &issuer.identity_sc, &signature, &gas_strategy, &confirmation_config, Duration::from_secs(60)).await?;
+            println!("Ownership transfer {} {}, tx {:?}", transfer.id, transfer.status, transfer.tx_hash);
+            Ok(())
+        },
+        Some(Commands::AddService { kind, endpoint, fragment, document }) => {
+            let update = DocumentUpdate { add_services: vec![ServiceEntry { kind, endpoint, fragment }], ..Default::default() };
+            update_document(&iota_state_data, &issuers, update, document).await
+        },
+        Some(Commands::RemoveService { fragment, document }) => {
+            let update = DocumentUpdate { remove_services: vec![fragment], ..Default::default() };
+            update_document(&iota_state_data, &issuers, update, document).await
+        },
+        Some(Commands::SetAlsoKnownAs { urls, document }) => {
+            let update = DocumentUpdate { also_known_as: Some(urls), ..Default::default() };
+            update_document(&iota_state_data, &issuers, update, document).await
+        },
+        Some(Commands::LinkDomain { document }) => {
+            let Some(domain_linkage) = DomainLinkage::new(&args.issuer_config.issuer_url, args.issuer_config.domain_linkage_validity_days) else {
+                anyhow::bail!("domain linkage disabled, ISSUER_URL must be an https domain and DOMAIN_LINKAGE_VALIDITY_DAYS above 0");
+            };
+            let service = ServiceEntry { kind: ServiceKind::LinkedDomain, endpoint: domain_linkage.origin().clone(), fragment: None };
+            let update = DocumentUpdate { add_services: vec![service], ..Default::default() };
+            update_document(&iota_state_data, &issuers, update, document).await
+        },
     }
 
 }
 
-async fn start_server(db_pool: Pool, 
-    sc_instance: web::Data<IdentityInstance<DynProvider>>, 
+/// Signer and settings of the contract transactions sent by the server
+struct TransactionSettings {
+    signer: LocalSigner<SigningKey>, // TODO: remove after debugging
+    gas_strategy: GasStrategy,
+    confirmation_config: ConfirmationConfig,
+}
+
+async fn start_server(db_pool: Database, 
+    challenge_store: web::Data<ChallengeStore>,
+    issuers: web::Data<IssuerRegistry>,
     iota_state_data: web::Data<IotaState>,
     issuer_config: IssuerConfig,
-    signer: LocalSigner<SigningKey>, // TODO: remove after debugging
+    transactions: TransactionSettings,
     http_config: HttpServerConfig) 
     -> Result<(), anyhow::Error> {
 
+        let TransactionSettings { signer, gas_strategy, confirmation_config } = transactions;
+
+        let issuance_queue = web::Data::new(IssuanceQueue::default());
+        tokio::task::spawn(issuance_worker(
+            db_pool.clone(),
+            issuers.clone(),
+            gas_strategy.clone(),
+            issuance_queue.clone(),
+            Duration::from_secs(issuer_config.issuance_poll_interval),
+        ));
+        tokio::task::spawn(reconciler(
+            db_pool.clone(),
+            issuers.clone(),
+            gas_strategy.clone(),
+            confirmation_config,
+            Duration::from_secs(issuer_config.reconcile_interval),
+            Duration::from_secs(issuer_config.outbox_alert_after),
+        ));
+
+        let rate_limit = &issuer_config.challenge_rate_limit;
+        let rate_limit_window = Duration::from_secs(rate_limit.challenge_limit_window);
+        let challenge_rate_limiters = web::Data::new(ChallengeRateLimiters {
+            per_did: RateLimiter::new(rate_limit.challenge_limit_per_did, rate_limit_window),
+            per_ip: RateLimiter::new(rate_limit.challenge_limit_per_ip, rate_limit_window),
+            trusted_proxies: rate_limit.challenge_trusted_proxies.clone(),
+        });
+
+        let maintenance_stats = web::Data::new(MaintenanceStats::default());
+        tokio::task::spawn(maintenance_scheduler(
+            db_pool.clone(),
+            issuers.clone(),
+            challenge_store.clone(),
+            challenge_rate_limiters.clone(),
+            maintenance_stats.clone(),
+            issuer_config.maintenance,
+        ));
+
+        let domain_linkage = DomainLinkage::new(&issuer_config.issuer_url, issuer_config.domain_linkage_validity_days);
+        if let Some(domain_linkage) = &domain_linkage {
+            for issuer in issuers.iter().filter(|issuer| !domain_linkage.is_linked(&issuer.document())) {
+                log::warn!("Issuer {} is not linked to {}, run link-domain to add the LinkedDomains service", issuer.name, domain_linkage.origin());
+            }
+        } else {
+            log::warn!("ISSUER_URL is not an https domain or DOMAIN_LINKAGE_VALIDITY_DAYS is 0, /.well-known/did-configuration.json disabled");
+        }
+        let domain_linkage = domain_linkage.map(web::Data::new);
+        let did_web = if issuer_config.did_web {
+            let did_web = DidWeb::new(&issuer_config.issuer_url)?;
+            log::info!("Serving {} at /.well-known/did.json", did_web.did());
+            Some(web::Data::new(did_web))
+        } else {
+            None
+        };
+
+        let admin_token = issuer_config.admin_token.clone().map(|token| web::Data::new(AdminToken(token)));
+        if admin_token.is_none() {
+            log::warn!("ADMIN_TOKEN not set, admin endpoints disabled");
+        }
+
         log::info!("Starting up on {}:{}", http_config.host_address, http_config.host_port);
 
         HttpServer::new(move || {
@@ -116,18 +354,37 @@ async fn start_server(db_pool: Pool,
                 .allowed_header(http::header::CONTENT_TYPE)
                 .max_age(3600);
 
-            App::new()
+            let mut app = App::new()
                 .app_data(web::Data::new(db_pool.clone()))
-                .app_data(sc_instance.clone())
+                .app_data(challenge_store.clone())
+                .app_data(issuers.clone())
                 .app_data(iota_state_data.clone())
-                .app_data(web::Data::new(issuer_config.issuer_url.clone()))
                 .app_data(web::Data::new(signer.clone()))
+                .app_data(web::Data::new(gas_strategy.clone()))
+                .app_data(issuance_queue.clone())
+                .app_data(web::Data::new(confirmation_config))
+                .app_data(challenge_rate_limiters.clone())
+                .app_data(maintenance_stats.clone());
+            if let Some(admin_token) = &admin_token {
+                app = app.app_data(admin_token.clone());
+            }
+            if let Some(domain_linkage) = &domain_linkage {
+                app = app.app_data(domain_linkage.clone());
+            }
+            if let Some(did_web) = &did_web {
+                app = app.app_data(did_web.clone());
+            }
+
+            // /api/{issuer} only matches the configured issuers, the plain /api serves the default one
+            let registry = issuers.clone();
+            app
                 .service(
-                    web::scope("/api")
-                        .configure(credentials_handler::scoped_config)
-                        .configure(challenges_handler::scoped_config)
-                        .configure(addresses_handler::scoped_config),
+                    web::scope("/api/{issuer}")
+                        .guard(guard::fn_guard(move |ctx| registry.is_issuer_path(ctx.head().uri.path())))
+                        .configure(api_config),
                 )
+                .service(web::scope("/api").configure(api_config))
+                .configure(well_known_handler::scoped_config)
                 .wrap(cors)
                 .wrap(Logger::default())
         })
@@ -137,24 +394,75 @@ async fn start_server(db_pool: Pool,
         .map_err(anyhow::Error::from)
 }
 
-async fn revoke_credential(identity_sc: IdentityInstance<DynProvider>, credential_id: i64) -> Result<(), anyhow::Error> {    
-    let call = identity_sc.revokeVC(U256::from(credential_id));
-    let receipt = call
-        .gas_price(10_000_000_000)
-        .send()
-        .await
-        .map_err(|err| IssuerError::ContractError(err.to_string()))?
-        .get_receipt()
-        .await
-        .map_err(|err| IssuerError::ContractError(err.to_string()))?;
-
-    // reading the log   
-    for log in receipt.logs() {
-        // finding the event
-        if let Ok(_) =  <VC_Revoked as SolEvent>::decode_log(&log.inner){
-            log::info!("VcRevoked event:\n{:?}", log);
-            return Ok(());
+async fn revoke_credential(
+    db_pool: Database,
+    issuer: &Issuer,
+    gas_strategy: &GasStrategy,
+    confirmation_config: ConfirmationConfig,
+    credential_id: i64
+) -> Result<(), anyhow::Error> {    
+    let db_client = db_pool.get().await?;
+    match outbox::revoke_vc(&db_client, issuer, credential_id, gas_strategy, confirmation_config.confirmation_depth, Duration::from_secs(60)).await? {
+        TxState::Confirmed => {
+            log::info!("Credential {} revoked", credential_id);
+            Ok(())
+        },
+        TxState::Pending | TxState::Dropped => Err(IssuerError::OtherError("revocation not confirmed yet, it will be followed by the reconciler".to_owned()).into()),
+        TxState::Failed(reason) => Err(IssuerError::ContractError(reason).into()),
+    }
+}
+
+/// Prints the diff of the update and publishes it when requested
+async fn update_document(
+    iota_state: &IotaState,
+    issuers: &IssuerRegistry,
+    update: DocumentUpdate,
+    args: DocumentCommandArgs,
+) -> Result<(), anyhow::Error> {
+    let issuer = issuers.resolve(args.issuer.as_deref())?;
+    let outcome = update_issuer_document(iota_state, &issuer, &update, args.publish).await?;
+    println!("DID document of issuer {} ({})", issuer.name, outcome.document.id());
+    for line in &outcome.diff {
+        println!("{}", line);
+    }
+    if outcome.published {
+        println!("Published");
+    } else {
+        println!("Not published, run again with --publish to publish it");
+    }
+    Ok(())
+}
+
+async fn deploy_contracts(
+    provider: &DynProvider,
+    dlt_config: &DLTConfig,
+    gas_strategy: &GasStrategy,
+    force: bool,
+) -> Result<(), anyhow::Error> {
+    let state_file = &dlt_config.contracts_state_file;
+    if let Some(state) = ContractsState::load(state_file)? {
+        if !force {
+            anyhow::bail!(
+                "Identity contract already deployed at {} (chain {}), see {}; use --force to deploy a new one",
+                state.identity_sc_address, state.chain_id, state_file.display()
+            );
         }
     }
-    Err(IssuerError::OtherError("no VcRevoked event found in the receipt".to_owned()).into())
-}
\ No newline at end of file
+
+    let chain_id = provider.get_chain_id().await?;
+    if chain_id != dlt_config.chain_id {
+        anyhow::bail!("RPC provider is on chain {}, CHAIN_ID is {}", chain_id, dlt_config.chain_id);
+    }
+
+    let state = deploy_identity(
+        provider,
+        chain_id,
+        gas_strategy,
+        dlt_config.confirmation_config.confirmation_depth,
+        Duration::from_secs(120),
+    ).await?;
+    state.save(state_file)?;
+    println!("Identity contract deployed at {} (block {}, tx {})", state.identity_sc_address, state.deployment_block, state.deployment_tx);
+    println!("Recorded in {}, it will be used on the next start", state_file.display());
+    Ok(())
+}
@@ -0,0 +1,36 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, web, Error};
+use actix_web_lab::middleware::Next;
+
+use crate::{errors::IssuerError, utils::configs::ConfigSecret};
+
+/// Bearer token required by the admin endpoints
+pub struct AdminToken(pub ConfigSecret);
+
+pub async fn verify_admin_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let admin_token = req.app_data::<web::Data<AdminToken>>()
+        .ok_or(IssuerError::MiddlewareError("Admin endpoints disabled".to_owned()))?;
+
+    let bearer = req.headers()
+        .get("authorization")
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|header_value| header_value.strip_prefix("Bearer "))
+        .ok_or(IssuerError::MiddlewareError("Admin token not found in the HTTP header".to_owned()))?;
+
+    if !constant_time_eq(bearer.as_bytes(), admin_token.0.value().as_bytes()) {
+        log::warn!("Admin request with an invalid token: {}", req.path());
+        return Err(IssuerError::MiddlewareError("Invalid admin token".to_owned()).into());
+    }
+
+    next.call(req).await
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -2,4 +2,5 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-pub mod ver_presentation_jwt;
\ No newline at end of file
+pub mod ver_presentation_jwt;
+pub mod admin_auth;
\ No newline at end of file
@@ -6,12 +6,11 @@ use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, Error
 use actix_web_lab::middleware::Next;
 
 use actix_web::web;
-use deadpool_postgres::Pool;
 use identity_eddsa_verifier::EdDSAJwsVerifier;
-use identity_iota::{core::{Object, Timestamp}, credential::{DecodedJwtPresentation, FailFast, Jwt, JwtCredentialValidationOptions, JwtCredentialValidator, JwtCredentialValidatorUtils, JwtPresentationValidationOptions, JwtPresentationValidator, JwtPresentationValidatorUtils, SubjectHolderRelationship}, did::{CoreDID, DID}, document::verifiable::JwsVerificationOptions, iota::IotaDocument, resolver::Resolver, verification::{jws::JwsHeader, jwu::decode_b64_json}};
-use std::str::FromStr;
+use identity_iota::{core::Object, credential::{DecodedJwtPresentation, FailFast, Jwt, JwtCredentialValidationOptions, JwtCredentialValidator, JwtCredentialValidatorUtils, JwtPresentationValidationOptions, JwtPresentationValidator, JwtPresentationValidatorUtils, SubjectHolderRelationship}, did::{CoreDID, DID}, document::verifiable::JwsVerificationOptions, iota::IotaDocument, resolver::Resolver, verification::{jws::JwsHeader, jwu::decode_b64_json}};
+use std::time::SystemTime;
 
-use crate::{errors::IssuerError, repository::operations::HoldersChallengesExt, utils::iota::IotaState};
+use crate::{errors::IssuerError, repository::{challenge_store::ChallengeStore, models::ChallengePurpose, operations::HoldersChallengesExt}, utils::{iota::IotaState, issuers::CurrentIssuer}};
 #[derive(Debug, Clone)]
 pub struct VerifiedPresentation{
     pub challenge: String,
@@ -25,8 +24,9 @@ pub async fn verify_presentation_jwt(
 ) -> Result<ServiceResponse<impl MessageBody>, Error> {
     // pre-processing
     log::info!("Hi from start 1. You requested: {}", req.path());
-    let db_pool = req.app_data::<web::Data<Pool>>().ok_or(IssuerError::MiddlewareError("no db pool".to_string()))?;
+    let challenge_store = req.app_data::<web::Data<ChallengeStore>>().ok_or(IssuerError::MiddlewareError("no challenge store".to_string()))?;
     let iota_state = req.app_data::<web::Data<IotaState>>().ok_or(IssuerError::MiddlewareError("no iota state".to_string()))?;
+    let issuer = CurrentIssuer::from_request_parts(req.request())?;
 
     log::info!("Resources initialized");
     // Extract the JWT from the request.
@@ -66,19 +66,17 @@ pub async fn verify_presentation_jwt(
         .map_err(|e| IssuerError::MiddlewareError(e.to_string()))?;
 
     // Recover the expected challenge from the database
-    let pg_client = db_pool.get().await.map_err(IssuerError::PoolError)?;
     log::info!("Holder did: {}", holder.id());
     // check and clean holder requests
-    let download_request = pg_client
-        .get_challenge(&holder.id().to_string(), &received_nonce.to_owned())
+    // presentations authorize revocations, nonces of the other flows are rejected.
+    // The nonce is consumed before validating the presentation, so it cannot be replayed,
+    // and only the challenges handed out by this issuer are found
+    let download_request = challenge_store
+        .consume_challenge(&holder.id().to_string(), received_nonce, ChallengePurpose::Revoke, &issuer.audience)
         .await?;
 
-    //check challenge expiration
-    let expiration = Timestamp::from_str(&download_request.expiration)
-      .map_err(|_| {IssuerError::OtherError("Unsupported timestamp format".to_owned())})?;
-
     // guard the code returning early if the challenge is expired
-    if Timestamp::now_utc() > expiration {
+    if SystemTime::now() > download_request.expiration {
         return Err(IssuerError::ChallengeExpired.into())
     }
     
@@ -101,6 +99,10 @@ pub async fn verify_presentation_jwt(
 
     let issuer_did: CoreDID = JwtCredentialValidatorUtils::extract_issuer_from_jwt(jwt_credential)
         .map_err(|_| IssuerError::MiddlewareError("Issuer DID not found".to_owned()))?;
+    // only the credentials of the addressed issuer can be revoked through its api
+    if issuer_did.to_string() != issuer.identity.did {
+        return Err(IssuerError::MiddlewareError("Credential not issued by this issuer".to_owned()).into())
+    }
     
     let issuer_document = resolver.resolve(&issuer_did)
         .await
@@ -0,0 +1,275 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use clap::ValueEnum;
+use redis::aio::ConnectionManager;
+
+use crate::errors::IssuerError;
+use crate::utils::configs::ChallengeStoreConfig;
+
+use super::database::Database;
+use super::models::{ChallengePurpose, HolderChallenge};
+use super::operations::HoldersChallengesExt;
+
+/// Backend of the holders challenges
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChallengeStoreKind {
+    /// holders_challenges table of the configured database, shared by every replica on Postgres
+    #[value(alias = "postgres")]
+    Database,
+    /// Map in the process memory, for single instance deployments and tests
+    Memory,
+    /// Keys with a TTL, shared by every replica
+    Redis,
+}
+
+/// Challenge store selected in the configuration
+pub enum ChallengeStore {
+    Database(DatabaseChallengeStore),
+    Memory(MemoryChallengeStore),
+    Redis(RedisChallengeStore),
+}
+
+impl ChallengeStore {
+    pub async fn new(config: &ChallengeStoreConfig, pool: Database) -> Result<Self, IssuerError> {
+        log::info!("Challenge store: {:?}", config.challenge_store);
+        Ok(match config.challenge_store {
+            ChallengeStoreKind::Database => ChallengeStore::Database(DatabaseChallengeStore(pool)),
+            ChallengeStoreKind::Memory => ChallengeStore::Memory(MemoryChallengeStore::default()),
+            ChallengeStoreKind::Redis => {
+                let url = config.redis_url.as_ref()
+                    .ok_or(IssuerError::ChallengeStoreError("REDIS_URL is required by the redis challenge store".to_owned()))?;
+                ChallengeStore::Redis(RedisChallengeStore::connect(&url.value()).await?)
+            }
+        })
+    }
+
+    fn inner(&self) -> &(dyn HoldersChallengesExt + Send + Sync) {
+        match self {
+            ChallengeStore::Database(store) => store,
+            ChallengeStore::Memory(store) => store,
+            ChallengeStore::Redis(store) => store,
+        }
+    }
+}
+
+#[async_trait]
+impl HoldersChallengesExt for ChallengeStore {
+    async fn consume_challenge(&self, did: &str, nonce: &str, purpose: ChallengePurpose, audience: &str) -> Result<HolderChallenge, IssuerError> {
+        self.inner().consume_challenge(did, nonce, purpose, audience).await
+    }
+
+    async fn insert_challenge(&self, holder_challenge: &HolderChallenge) -> Result<HolderChallenge, IssuerError> {
+        self.inner().insert_challenge(holder_challenge).await
+    }
+
+    async fn cleanup_challenges(&self) -> Result<u64, anyhow::Error> {
+        self.inner().cleanup_challenges().await
+    }
+}
+
+fn reject_challenge(did: &str, purpose: ChallengePurpose) -> IssuerError {
+    log::warn!("Rejected unknown or already used {} challenge of {}", purpose.as_str(), did);
+    IssuerError::NonExistingRequestError
+}
+
+/// Database store, a client is taken from the pool for every call
+pub struct DatabaseChallengeStore(pub Database);
+
+#[async_trait]
+impl HoldersChallengesExt for DatabaseChallengeStore {
+    async fn consume_challenge(&self, did: &str, nonce: &str, purpose: ChallengePurpose, audience: &str) -> Result<HolderChallenge, IssuerError> {
+        self.0.get().await?.consume_challenge(did, nonce, purpose, audience).await
+    }
+
+    async fn insert_challenge(&self, holder_challenge: &HolderChallenge) -> Result<HolderChallenge, IssuerError> {
+        self.0.get().await?.insert_challenge(holder_challenge).await
+    }
+
+    async fn cleanup_challenges(&self) -> Result<u64, anyhow::Error> {
+        self.0.get().await?.cleanup_challenges().await
+    }
+}
+
+/// Holder, purpose and audience of a challenge, a holder has at most one pending challenge per flow and issuer
+type ChallengeKey = (String, String, String);
+
+/// In-process store, challenges are keyed by holder, purpose and audience
+#[derive(Default)]
+pub struct MemoryChallengeStore {
+    challenges: Mutex<HashMap<ChallengeKey, HolderChallenge>>,
+}
+
+#[async_trait]
+impl HoldersChallengesExt for MemoryChallengeStore {
+    async fn consume_challenge(&self, did: &str, nonce: &str, purpose: ChallengePurpose, audience: &str) -> Result<HolderChallenge, IssuerError> {
+        let mut challenges = self.challenges.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let key = (did.to_owned(), purpose.as_str().to_owned(), audience.to_owned());
+        match challenges.get(&key) {
+            Some(challenge) if challenge.challenge == nonce => Ok(challenges.remove(&key).expect("challenge just found")),
+            _ => Err(reject_challenge(did, purpose)),
+        }
+    }
+
+    async fn insert_challenge(&self, holder_challenge: &HolderChallenge) -> Result<HolderChallenge, IssuerError> {
+        let mut challenges = self.challenges.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let key = (holder_challenge.did_holder.clone(), holder_challenge.purpose.clone(), holder_challenge.audience.clone());
+        // an expired challenge does not count as pending
+        if challenges.get(&key).is_some_and(|pending| pending.expiration >= SystemTime::now()) {
+            return Err(IssuerError::ChallengePendingError);
+        }
+        challenges.insert(key, holder_challenge.clone());
+        Ok(holder_challenge.clone())
+    }
+
+    async fn cleanup_challenges(&self) -> Result<u64, anyhow::Error> {
+        let now = SystemTime::now();
+        let mut challenges = self.challenges.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let before = challenges.len();
+        challenges.retain(|_, challenge| challenge.expiration >= now);
+        Ok((before - challenges.len()) as u64)
+    }
+}
+
+/// Returns and deletes the stored challenge only if its nonce matches
+const CONSUME_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if value and cjson.decode(value).challenge == ARGV[1] then
+    redis.call('DEL', KEYS[1])
+    return value
+end
+return false
+"#;
+
+/// Redis store, one key per holder, purpose and audience expiring with the challenge
+pub struct RedisChallengeStore {
+    connection: ConnectionManager,
+}
+
+impl RedisChallengeStore {
+    pub async fn connect(url: &str) -> Result<Self, IssuerError> {
+        let client = redis::Client::open(url)?;
+        Ok(Self { connection: client.get_connection_manager().await? })
+    }
+
+    fn key(did: &str, purpose: &str, audience: &str) -> String {
+        format!("issuer:challenge:{}:{}:{}", purpose, audience, did)
+    }
+}
+
+#[async_trait]
+impl HoldersChallengesExt for RedisChallengeStore {
+    async fn consume_challenge(&self, did: &str, nonce: &str, purpose: ChallengePurpose, audience: &str) -> Result<HolderChallenge, IssuerError> {
+        let value: Option<String> = redis::Script::new(CONSUME_SCRIPT)
+            .key(Self::key(did, purpose.as_str(), audience))
+            .arg(nonce)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        let value = value.ok_or_else(|| reject_challenge(did, purpose))?;
+        serde_json::from_str(&value)
+            .map_err(|err| IssuerError::ChallengeStoreError(err.to_string()))
+    }
+
+    async fn insert_challenge(&self, holder_challenge: &HolderChallenge) -> Result<HolderChallenge, IssuerError> {
+        let ttl = holder_challenge.expiration.duration_since(SystemTime::now())
+            .map_err(|_| IssuerError::ChallengeStoreError("challenge already expired".to_owned()))?;
+        let value = serde_json::to_string(holder_challenge)
+            .map_err(|err| IssuerError::ChallengeStoreError(err.to_string()))?;
+
+        // NX keeps a single pending challenge, expired keys are dropped by Redis
+        let stored: Option<String> = redis::cmd("SET")
+            .arg(Self::key(&holder_challenge.did_holder, &holder_challenge.purpose, &holder_challenge.audience))
+            .arg(value)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        match stored {
+            Some(_) => Ok(holder_challenge.clone()),
+            None => Err(IssuerError::ChallengePendingError),
+        }
+    }
+
+    async fn cleanup_challenges(&self) -> Result<u64, anyhow::Error> {
+        // keys expire on their own
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const AUDIENCE: &str = "https://issuer.example/";
+
+    fn challenge(nonce: &str, purpose: ChallengePurpose, expiration: SystemTime) -> HolderChallenge {
+        HolderChallenge {
+            did_holder: "did:iota:holder".to_owned(),
+            challenge: nonce.to_owned(),
+            expiration,
+            purpose: purpose.as_str().to_owned(),
+            audience: AUDIENCE.to_owned(),
+        }
+    }
+
+    fn in_a_minute() -> SystemTime {
+        SystemTime::now() + Duration::from_secs(60)
+    }
+
+    #[tokio::test]
+    async fn memory_store_consumes_a_challenge_once() {
+        let store = MemoryChallengeStore::default();
+        let issued = store.insert_challenge(&challenge("nonce", ChallengePurpose::Issue, in_a_minute())).await.unwrap();
+        let (did, audience) = (issued.did_holder.clone(), AUDIENCE.to_owned());
+
+        assert!(store.consume_challenge(&did, "other", ChallengePurpose::Issue, &audience).await.is_err());
+        assert!(store.consume_challenge(&did, &issued.challenge, ChallengePurpose::Revoke, &audience).await.is_err());
+        assert!(store.consume_challenge(&did, &issued.challenge, ChallengePurpose::Issue, "https://other.example/").await.is_err());
+
+        let consumed = store.consume_challenge(&did, &issued.challenge, ChallengePurpose::Issue, &audience).await.unwrap();
+        assert_eq!(consumed.challenge, "nonce");
+        assert!(matches!(
+            store.consume_challenge(&did, &issued.challenge, ChallengePurpose::Issue, &audience).await,
+            Err(IssuerError::NonExistingRequestError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn memory_store_keeps_one_pending_challenge_per_flow_and_audience() {
+        let store = MemoryChallengeStore::default();
+        store.insert_challenge(&challenge("first", ChallengePurpose::Issue, in_a_minute())).await.unwrap();
+        assert!(matches!(
+            store.insert_challenge(&challenge("second", ChallengePurpose::Issue, in_a_minute())).await,
+            Err(IssuerError::ChallengePendingError)
+        ));
+        store.insert_challenge(&challenge("revoke", ChallengePurpose::Revoke, in_a_minute())).await.unwrap();
+
+        let mut other_issuer = challenge("other", ChallengePurpose::Issue, in_a_minute());
+        other_issuer.audience = "https://issuer.example/api/other/".to_owned();
+        store.insert_challenge(&other_issuer).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn memory_store_replaces_and_cleans_up_expired_challenges() {
+        let store = MemoryChallengeStore::default();
+        let expired = SystemTime::now() - Duration::from_secs(1);
+        store.insert_challenge(&challenge("expired", ChallengePurpose::Issue, expired)).await.unwrap();
+        // an expired challenge does not block a new one
+        store.insert_challenge(&challenge("fresh", ChallengePurpose::Issue, in_a_minute())).await.unwrap();
+        store.insert_challenge(&challenge("expired", ChallengePurpose::Revoke, expired)).await.unwrap();
+
+        assert_eq!(store.cleanup_challenges().await.unwrap(), 1);
+        assert_eq!(store.cleanup_challenges().await.unwrap(), 0);
+        let did = "did:iota:holder".to_owned();
+        assert!(store.consume_challenge(&did, "fresh", ChallengePurpose::Issue, AUDIENCE).await.is_ok());
+    }
+}
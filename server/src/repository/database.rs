@@ -0,0 +1,209 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::SystemTime;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::errors::IssuerError;
+use crate::utils::configs::DatabaseConfig;
+
+use super::migrations::{run_migrations, run_sqlite_migrations};
+use super::models::{ChallengePurpose, ContractEvent, ContractOperation, HolderChallenge, IndexerCursor, IssuanceJob, IssuerIdentity, JobStatus, OwnershipTransfer, VcIdReservation};
+use super::operations::{ContractEventsExt, ContractOperationsExt, HoldersChallengesExt, IssuanceJobsExt, IssuerIdentityExt, OwnershipTransfersExt, VcIdReservationsExt};
+use super::sqlite_operations::SqliteClient;
+use super::{postgres_repo, sqlite_repo};
+
+/// Connection pool of the storage backend selected by DATABASE_URL
+#[derive(Clone)]
+pub enum Database {
+    Postgres(deadpool_postgres::Pool),
+    Sqlite(deadpool_sqlite::Pool),
+}
+
+/// Connection taken from a [`Database`], implements every repository trait
+pub enum DbClient {
+    Postgres(deadpool_postgres::Client),
+    Sqlite(SqliteClient),
+}
+
+impl Database {
+    /// SQLite for `sqlite:` urls, Postgres otherwise
+    pub async fn init(configuration: DatabaseConfig) -> Result<Self> {
+        let sqlite_url = configuration.database_url.as_ref()
+            .map(|url| url.value())
+            .filter(|url| url.starts_with("sqlite:"));
+        match sqlite_url {
+            Some(url) => Ok(Database::Sqlite(sqlite_repo::init(&url, &configuration).await?)),
+            None => Ok(Database::Postgres(postgres_repo::init(configuration).await?)),
+        }
+    }
+
+    pub async fn get(&self) -> Result<DbClient, IssuerError> {
+        match self {
+            Database::Postgres(pool) => Ok(DbClient::Postgres(pool.get().await?)),
+            Database::Sqlite(pool) => Ok(DbClient::Sqlite(pool.get().await?)),
+        }
+    }
+
+    /// Brings the schema to the latest version, returns the versions applied by this call
+    pub async fn migrate(&self) -> Result<Vec<i32>, IssuerError> {
+        match self {
+            Database::Postgres(pool) => run_migrations(pool).await,
+            Database::Sqlite(pool) => run_sqlite_migrations(pool).await,
+        }
+    }
+}
+
+/// Forwards a repository call to the backend of the client
+macro_rules! dispatch {
+    ($client:expr, $method:ident($($arg:expr),*)) => {
+        match $client {
+            DbClient::Postgres(client) => client.$method($($arg),*).await,
+            DbClient::Sqlite(client) => client.$method($($arg),*).await,
+        }
+    };
+}
+
+#[async_trait]
+impl IssuerIdentityExt for DbClient {
+    async fn get_identity_did(&self, name: &str) -> Result<IssuerIdentity, IssuerError> {
+        dispatch!(self, get_identity_did(name))
+    }
+
+    async fn insert_identity_issuer(&self, identity: &IssuerIdentity) -> Result<IssuerIdentity, IssuerError> {
+        dispatch!(self, insert_identity_issuer(identity))
+    }
+}
+
+#[async_trait]
+impl HoldersChallengesExt for DbClient {
+    async fn consume_challenge(&self, did: &str, nonce: &str, purpose: ChallengePurpose, audience: &str) -> Result<HolderChallenge, IssuerError> {
+        dispatch!(self, consume_challenge(did, nonce, purpose, audience))
+    }
+
+    async fn insert_challenge(&self, holder_challenge: &HolderChallenge) -> Result<HolderChallenge, IssuerError> {
+        dispatch!(self, insert_challenge(holder_challenge))
+    }
+
+    async fn cleanup_challenges(&self) -> Result<u64, anyhow::Error> {
+        dispatch!(self, cleanup_challenges())
+    }
+}
+
+#[async_trait]
+impl IssuanceJobsExt for DbClient {
+    async fn insert_issuance_job(&self, job: &IssuanceJob) -> Result<IssuanceJob, IssuerError> {
+        dispatch!(self, insert_issuance_job(job))
+    }
+
+    async fn get_issuance_job(&self, id: &str) -> Result<IssuanceJob, IssuerError> {
+        dispatch!(self, get_issuance_job(id))
+    }
+
+    async fn get_issuance_jobs_by_status(&self, status: JobStatus) -> Result<Vec<IssuanceJob>, IssuerError> {
+        dispatch!(self, get_issuance_jobs_by_status(status))
+    }
+
+    async fn update_issuance_job(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        dispatch!(self, update_issuance_job(id, status, tx_hash, error))
+    }
+
+    async fn prune_issuance_jobs(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        dispatch!(self, prune_issuance_jobs(before))
+    }
+}
+
+#[async_trait]
+impl ContractOperationsExt for DbClient {
+    async fn insert_operation(&self, operation: &ContractOperation) -> Result<ContractOperation, IssuerError> {
+        dispatch!(self, insert_operation(operation))
+    }
+
+    async fn get_operations_by_status(&self, status: JobStatus) -> Result<Vec<ContractOperation>, IssuerError> {
+        dispatch!(self, get_operations_by_status(status))
+    }
+
+    async fn get_latest_operation_by_job(&self, job_id: &str) -> Result<ContractOperation, IssuerError> {
+        dispatch!(self, get_latest_operation_by_job(job_id))
+    }
+
+    async fn update_operation(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        dispatch!(self, update_operation(id, status, tx_hash, error))
+    }
+
+    async fn resubmit_operation(&self, id: &str, tx_hash: &str) -> Result<(), IssuerError> {
+        dispatch!(self, resubmit_operation(id, tx_hash))
+    }
+
+    async fn mark_operation_alerted(&self, id: &str) -> Result<(), IssuerError> {
+        dispatch!(self, mark_operation_alerted(id))
+    }
+
+    async fn prune_operations(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        dispatch!(self, prune_operations(before))
+    }
+}
+
+#[async_trait]
+impl ContractEventsExt for DbClient {
+    async fn insert_event(&self, event: &ContractEvent) -> Result<(), IssuerError> {
+        dispatch!(self, insert_event(event))
+    }
+
+    async fn remove_events_from(&self, contract: &str, block_number: i64) -> Result<(), IssuerError> {
+        dispatch!(self, remove_events_from(contract, block_number))
+    }
+
+    async fn get_events_by_vc_id(&self, contract: &str, vc_id: &str) -> Result<Vec<ContractEvent>, IssuerError> {
+        dispatch!(self, get_events_by_vc_id(contract, vc_id))
+    }
+
+    async fn get_event_block_before(&self, contract: &str, block_number: i64) -> Result<IndexerCursor, IssuerError> {
+        dispatch!(self, get_event_block_before(contract, block_number))
+    }
+
+    async fn get_indexer_cursor(&self, contract: &str) -> Result<IndexerCursor, IssuerError> {
+        dispatch!(self, get_indexer_cursor(contract))
+    }
+
+    async fn set_indexer_cursor(&self, cursor: &IndexerCursor) -> Result<(), IssuerError> {
+        dispatch!(self, set_indexer_cursor(cursor))
+    }
+}
+
+#[async_trait]
+impl VcIdReservationsExt for DbClient {
+    async fn reserve_vc_id(&self, issuer: &str, first_free_id: i64, job_id: &str) -> Result<VcIdReservation, IssuerError> {
+        dispatch!(self, reserve_vc_id(issuer, first_free_id, job_id))
+    }
+
+    async fn release_vc_id(&self, job_id: &str) -> Result<(), IssuerError> {
+        dispatch!(self, release_vc_id(job_id))
+    }
+
+    async fn prune_vc_id_reservations(&self, issuer: &str, first_free_id: i64) -> Result<u64, IssuerError> {
+        dispatch!(self, prune_vc_id_reservations(issuer, first_free_id))
+    }
+}
+
+#[async_trait]
+impl OwnershipTransfersExt for DbClient {
+    async fn insert_ownership_transfer(&self, transfer: &OwnershipTransfer) -> Result<OwnershipTransfer, IssuerError> {
+        dispatch!(self, insert_ownership_transfer(transfer))
+    }
+
+    async fn get_latest_ownership_transfer(&self, contract: &str, status: JobStatus) -> Result<OwnershipTransfer, IssuerError> {
+        dispatch!(self, get_latest_ownership_transfer(contract, status))
+    }
+
+    async fn update_ownership_transfer(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        dispatch!(self, update_ownership_transfer(id, status, tx_hash, error))
+    }
+
+    async fn prune_ownership_transfers(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        dispatch!(self, prune_ownership_transfers(before))
+    }
+}
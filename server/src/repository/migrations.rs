@@ -0,0 +1,177 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::SystemTime;
+
+use deadpool_postgres::{Client as PostgresClient, Pool};
+use rusqlite::params;
+
+use crate::errors::IssuerError;
+
+use super::sqlite_operations::{interact, to_millis};
+
+/// Versioned change of the database schema, embedded in the binary
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Applied in order, a released migration must never change: add a new one instead
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "baseline", sql: include_str!("./migrations/V1__baseline.sql") },
+    Migration { version: 2, name: "issuance_jobs_and_contract_state", sql: include_str!("./migrations/V2__issuance_jobs_and_contract_state.sql") },
+    Migration { version: 3, name: "timestamptz", sql: include_str!("./migrations/V3__timestamptz.sql") },
+    Migration { version: 4, name: "multi_tenant", sql: include_str!("./migrations/V4__multi_tenant.sql") },
+    Migration { version: 5, name: "challenge_audience", sql: include_str!("./migrations/V5__challenge_audience.sql") },
+];
+
+/// Schema of the SQLite backend, versioned on its own
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "schema", sql: include_str!("./migrations/sqlite/V1__schema.sql") },
+    Migration { version: 2, name: "multi_tenant", sql: include_str!("./migrations/sqlite/V2__multi_tenant.sql") },
+    Migration { version: 3, name: "challenge_audience", sql: include_str!("./migrations/sqlite/V3__challenge_audience.sql") },
+];
+
+/// Key of the advisory lock held while migrating, so that replicas starting together do not race
+const MIGRATION_LOCK_KEY: i64 = 0x5ed1_0042;
+
+/// Brings the schema to the latest version, returns the versions applied by this call
+pub async fn run_migrations(pool: &Pool) -> Result<Vec<i32>, IssuerError> {
+    let mut client = pool.get().await?;
+    client.batch_execute(include_str!("./sql/schema_migrations_init.sql")).await?;
+
+    client.execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_KEY]).await?;
+    let applied = apply_pending(&mut client).await;
+    client.execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_KEY]).await?;
+
+    let applied = applied?;
+    match applied.last() {
+        Some(version) => log::info!("Database schema migrated to version {}", version),
+        None => log::info!("Database schema up to date"),
+    }
+    Ok(applied)
+}
+
+/// Each migration runs in its own transaction together with its bookkeeping row
+async fn apply_pending(client: &mut PostgresClient) -> Result<Vec<i32>, IssuerError> {
+    let current: i32 = client.query_one(include_str!("./sql/schema_migrations_current.sql"), &[]).await?.get(0);
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        log::info!("Applying migration V{}__{}", migration.version, migration.name);
+        let transaction = client.transaction().await?;
+        transaction.batch_execute(migration.sql).await?;
+        transaction.execute(include_str!("./sql/schema_migrations_insert.sql"), &[&migration.version, &migration.name]).await?;
+        transaction.commit().await?;
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
+
+/// SQLite counterpart of [`run_migrations`], the write transactions are serialized by SQLite itself
+pub async fn run_sqlite_migrations(pool: &deadpool_sqlite::Pool) -> Result<Vec<i32>, IssuerError> {
+    let client = pool.get().await?;
+    let applied = interact(&client, |conn| {
+        conn.execute_batch(include_str!("./sql/sqlite/schema_migrations_init.sql"))?;
+        let current: i32 = conn.query_row(include_str!("./sql/sqlite/schema_migrations_current.sql"), [], |row| row.get(0))?;
+
+        let mut applied = Vec::new();
+        for migration in SQLITE_MIGRATIONS.iter().filter(|migration| migration.version > current) {
+            log::info!("Applying SQLite migration V{}__{}", migration.version, migration.name);
+            let transaction = conn.transaction()?;
+            transaction.execute_batch(migration.sql)?;
+            transaction.execute(
+                include_str!("./sql/sqlite/schema_migrations_insert.sql"),
+                params![migration.version, migration.name, to_millis(SystemTime::now())],
+            )?;
+            transaction.commit()?;
+            applied.push(migration.version);
+        }
+        Ok(applied)
+    }).await?;
+
+    match applied.last() {
+        Some(version) => log::info!("SQLite schema migrated to version {}", version),
+        None => log::info!("SQLite schema up to date"),
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::repository::database::Database;
+    use crate::repository::models::{ChallengePurpose, HolderChallenge};
+    use crate::repository::operations::HoldersChallengesExt;
+    use crate::repository::sqlite_repo;
+    use crate::utils::configs::DatabaseConfig;
+
+    use super::*;
+
+    async fn memory_database() -> Database {
+        let configuration = DatabaseConfig {
+            database_url: Some("sqlite::memory:".parse().unwrap()),
+            db_host: None,
+            db_port: None,
+            db_name: None,
+            db_user: None,
+            db_password: None,
+            db_max_pool_size: 16,
+            db_connect_timeout: 10,
+            db_pool_wait_timeout: 30,
+            db_ssl_mode: None,
+            db_ssl_root_cert: None,
+            db_ssl_client_cert: None,
+            db_ssl_client_key: None,
+        };
+        Database::Sqlite(sqlite_repo::init("sqlite::memory:", &configuration).await.unwrap())
+    }
+
+    fn challenge(audience: &str) -> HolderChallenge {
+        HolderChallenge {
+            did_holder: "did:iota:holder".to_owned(),
+            challenge: format!("nonce for {}", audience),
+            expiration: SystemTime::now() + Duration::from_secs(60),
+            purpose: ChallengePurpose::Issue.as_str().to_owned(),
+            audience: audience.to_owned(),
+        }
+    }
+
+    #[test]
+    fn migration_versions_are_sequential() {
+        for migrations in [MIGRATIONS, SQLITE_MIGRATIONS] {
+            for (index, migration) in migrations.iter().enumerate() {
+                assert_eq!(migration.version, index as i32 + 1, "{}", migration.name);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn sqlite_migrations_apply_once() {
+        let database = memory_database().await;
+        let all: Vec<i32> = SQLITE_MIGRATIONS.iter().map(|migration| migration.version).collect();
+        assert_eq!(database.migrate().await.unwrap(), all);
+        assert!(database.migrate().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sqlite_schema_keys_challenges_by_audience() {
+        let database = memory_database().await;
+        database.migrate().await.unwrap();
+        let db_client = database.get().await.unwrap();
+
+        let (first, second) = (challenge("https://issuer.example/"), challenge("https://issuer.example/api/other/"));
+        db_client.insert_challenge(&first).await.unwrap();
+        db_client.insert_challenge(&second).await.unwrap();
+        assert!(matches!(db_client.insert_challenge(&first).await, Err(IssuerError::ChallengePendingError)));
+
+        // a nonce is found only with the audience it was handed out for
+        assert!(db_client.consume_challenge(&first.did_holder, &first.challenge, ChallengePurpose::Issue, &second.audience).await.is_err());
+        let consumed = db_client.consume_challenge(&first.did_holder, &first.challenge, ChallengePurpose::Issue, &first.audience).await.unwrap();
+        assert_eq!(consumed.audience, first.audience);
+        assert!(db_client.consume_challenge(&first.did_holder, &first.challenge, ChallengePurpose::Issue, &first.audience).await.is_err());
+    }
+}
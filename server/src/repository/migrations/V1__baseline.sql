@@ -2,12 +2,13 @@
 
 -- SPDX-License-Identifier: GPL-3.0-or-later
 
-CREATE TABLE identities (
+-- Schema of the databases created with the former dbinit.sql
+CREATE TABLE IF NOT EXISTS identities (
     did text PRIMARY KEY,
     fragment text NOT NULL
 );
 
-CREATE TABLE holders_challenges (
+CREATE TABLE IF NOT EXISTS holders_challenges (
     did_holder          TEXT NOT NULL,
     challenge           TEXT NOT NULL,
     expiration			TEXT NOT NULL
@@ -0,0 +1,84 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- Challenges live for a minute, the pending ones can be dropped to add the new columns
+DELETE FROM holders_challenges;
+ALTER TABLE holders_challenges
+    ADD COLUMN IF NOT EXISTS purpose TEXT NOT NULL,
+    ADD COLUMN IF NOT EXISTS audience TEXT NOT NULL;
+
+-- a single pending challenge per holder and purpose
+CREATE UNIQUE INDEX IF NOT EXISTS holders_challenges_did_idx ON holders_challenges(did_holder, purpose);
+
+CREATE TABLE IF NOT EXISTS issuance_jobs (
+    id                  TEXT PRIMARY KEY,
+    did_holder          TEXT NOT NULL,
+    credential_id       TEXT NOT NULL,
+    credential_jwt      TEXT NOT NULL,
+    wallet_signature    TEXT NOT NULL,
+    wallet_address      TEXT NOT NULL,
+    challenge           TEXT NOT NULL,
+    signature_scheme    TEXT NOT NULL,
+    issuance_date       BIGINT NOT NULL,
+    expiration_date     BIGINT NOT NULL,
+    status              TEXT NOT NULL,
+    tx_hash             TEXT,
+    error               TEXT,
+    created_at          TEXT NOT NULL,
+    updated_at          TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS contract_operations (
+    id                  TEXT PRIMARY KEY,
+    kind                TEXT NOT NULL,
+    credential_id       TEXT NOT NULL,
+    job_id              TEXT REFERENCES issuance_jobs(id),
+    status              TEXT NOT NULL,
+    tx_hash             TEXT,
+    error               TEXT,
+    attempts            INTEGER NOT NULL DEFAULT 0,
+    alerted             BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at          TEXT NOT NULL,
+    updated_at          TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS contract_events (
+    contract            TEXT NOT NULL,
+    block_number        BIGINT NOT NULL,
+    block_hash          TEXT NOT NULL,
+    tx_hash             TEXT NOT NULL,
+    log_index           BIGINT NOT NULL,
+    event               TEXT NOT NULL,
+    vc_id               TEXT,
+    account             TEXT,
+    data                TEXT NOT NULL,
+    PRIMARY KEY (tx_hash, log_index)
+);
+
+CREATE INDEX IF NOT EXISTS contract_events_vc_id_idx ON contract_events(contract, vc_id);
+
+CREATE TABLE IF NOT EXISTS indexer_cursors (
+    contract            TEXT PRIMARY KEY,
+    block_number        BIGINT NOT NULL,
+    block_hash          TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS vc_id_reservations (
+    vc_id               BIGINT PRIMARY KEY,
+    job_id              TEXT NOT NULL UNIQUE,
+    reserved_at         TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ownership_transfers (
+    id                  TEXT PRIMARY KEY,
+    contract            TEXT NOT NULL,
+    current_owner       TEXT NOT NULL,
+    new_owner           TEXT NOT NULL,
+    challenge           TEXT NOT NULL,
+    status              TEXT NOT NULL,
+    tx_hash             TEXT,
+    error               TEXT,
+    created_at          TEXT NOT NULL,
+    updated_at          TEXT NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1,28 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- Timestamps stored as rfc3339 TEXT become timestamptz
+ALTER TABLE holders_challenges
+    ALTER COLUMN expiration TYPE TIMESTAMPTZ USING expiration::timestamptz;
+DROP INDEX IF EXISTS holders_challenges_did_idx;
+ALTER TABLE holders_challenges ADD PRIMARY KEY (did_holder, purpose);
+CREATE INDEX holders_challenges_expiration_idx ON holders_challenges(expiration);
+
+ALTER TABLE issuance_jobs
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at::timestamptz,
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING updated_at::timestamptz;
+CREATE INDEX issuance_jobs_status_idx ON issuance_jobs(status, created_at);
+
+ALTER TABLE contract_operations
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at::timestamptz,
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING updated_at::timestamptz;
+CREATE INDEX contract_operations_status_idx ON contract_operations(status, created_at);
+
+ALTER TABLE vc_id_reservations
+    ALTER COLUMN reserved_at TYPE TIMESTAMPTZ USING reserved_at::timestamptz;
+
+ALTER TABLE ownership_transfers
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at::timestamptz,
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING updated_at::timestamptz;
+CREATE INDEX ownership_transfers_contract_idx ON ownership_transfers(contract, status, created_at);
\ No newline at end of file
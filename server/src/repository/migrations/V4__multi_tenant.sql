@@ -0,0 +1,20 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- Several named issuers in one deployment, the existing rows belong to the default issuer
+ALTER TABLE identities ADD COLUMN IF NOT EXISTS name TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE identities ALTER COLUMN name DROP DEFAULT;
+CREATE UNIQUE INDEX IF NOT EXISTS identities_name_idx ON identities(name);
+
+ALTER TABLE issuance_jobs ADD COLUMN IF NOT EXISTS issuer TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE issuance_jobs ALTER COLUMN issuer DROP DEFAULT;
+
+ALTER TABLE contract_operations ADD COLUMN IF NOT EXISTS issuer TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE contract_operations ALTER COLUMN issuer DROP DEFAULT;
+
+-- each issuer has its own contract, hence its own VC ids
+ALTER TABLE vc_id_reservations ADD COLUMN IF NOT EXISTS issuer TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE vc_id_reservations ALTER COLUMN issuer DROP DEFAULT;
+ALTER TABLE vc_id_reservations DROP CONSTRAINT IF EXISTS vc_id_reservations_pkey;
+ALTER TABLE vc_id_reservations ADD PRIMARY KEY (issuer, vc_id);
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- A holder may have a pending challenge with every issuer of the deployment
+ALTER TABLE holders_challenges DROP CONSTRAINT IF EXISTS holders_challenges_pkey;
+ALTER TABLE holders_challenges ADD PRIMARY KEY (did_holder, purpose, audience);
\ No newline at end of file
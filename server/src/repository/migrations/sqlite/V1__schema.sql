@@ -0,0 +1,94 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- SQLite counterpart of the Postgres migrations up to V3, timestamps are unix milliseconds
+
+CREATE TABLE IF NOT EXISTS identities (
+    did                 TEXT PRIMARY KEY,
+    fragment            TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS holders_challenges (
+    did_holder          TEXT NOT NULL,
+    challenge           TEXT NOT NULL,
+    expiration          INTEGER NOT NULL,
+    purpose             TEXT NOT NULL,
+    audience            TEXT NOT NULL,
+    PRIMARY KEY (did_holder, purpose)
+);
+CREATE INDEX IF NOT EXISTS holders_challenges_expiration_idx ON holders_challenges(expiration);
+
+CREATE TABLE IF NOT EXISTS issuance_jobs (
+    id                  TEXT PRIMARY KEY,
+    did_holder          TEXT NOT NULL,
+    credential_id       TEXT NOT NULL,
+    credential_jwt      TEXT NOT NULL,
+    wallet_signature    TEXT NOT NULL,
+    wallet_address      TEXT NOT NULL,
+    challenge           TEXT NOT NULL,
+    signature_scheme    TEXT NOT NULL,
+    issuance_date       INTEGER NOT NULL,
+    expiration_date     INTEGER NOT NULL,
+    status              TEXT NOT NULL,
+    tx_hash             TEXT,
+    error               TEXT,
+    created_at          INTEGER NOT NULL,
+    updated_at          INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS issuance_jobs_status_idx ON issuance_jobs(status, created_at);
+
+CREATE TABLE IF NOT EXISTS contract_operations (
+    id                  TEXT PRIMARY KEY,
+    kind                TEXT NOT NULL,
+    credential_id       TEXT NOT NULL,
+    job_id              TEXT REFERENCES issuance_jobs(id),
+    status              TEXT NOT NULL,
+    tx_hash             TEXT,
+    error               TEXT,
+    attempts            INTEGER NOT NULL DEFAULT 0,
+    alerted             INTEGER NOT NULL DEFAULT 0,
+    created_at          INTEGER NOT NULL,
+    updated_at          INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS contract_operations_status_idx ON contract_operations(status, created_at);
+
+CREATE TABLE IF NOT EXISTS contract_events (
+    contract            TEXT NOT NULL,
+    block_number        INTEGER NOT NULL,
+    block_hash          TEXT NOT NULL,
+    tx_hash             TEXT NOT NULL,
+    log_index           INTEGER NOT NULL,
+    event               TEXT NOT NULL,
+    vc_id               TEXT,
+    account             TEXT,
+    data                TEXT NOT NULL,
+    PRIMARY KEY (tx_hash, log_index)
+);
+CREATE INDEX IF NOT EXISTS contract_events_vc_id_idx ON contract_events(contract, vc_id);
+
+CREATE TABLE IF NOT EXISTS indexer_cursors (
+    contract            TEXT PRIMARY KEY,
+    block_number        INTEGER NOT NULL,
+    block_hash          TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS vc_id_reservations (
+    vc_id               BIGINT NOT NULL PRIMARY KEY,
+    job_id              TEXT NOT NULL UNIQUE,
+    reserved_at         INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ownership_transfers (
+    id                  TEXT PRIMARY KEY,
+    contract            TEXT NOT NULL,
+    current_owner       TEXT NOT NULL,
+    new_owner           TEXT NOT NULL,
+    challenge           TEXT NOT NULL,
+    status              TEXT NOT NULL,
+    tx_hash             TEXT,
+    error               TEXT,
+    created_at          INTEGER NOT NULL,
+    updated_at          INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS ownership_transfers_contract_idx ON ownership_transfers(contract, status, created_at);
\ No newline at end of file
@@ -0,0 +1,24 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- Several named issuers in one deployment, the existing rows belong to the default issuer
+ALTER TABLE identities ADD COLUMN name TEXT NOT NULL DEFAULT 'default';
+CREATE UNIQUE INDEX IF NOT EXISTS identities_name_idx ON identities(name);
+
+ALTER TABLE issuance_jobs ADD COLUMN issuer TEXT NOT NULL DEFAULT 'default';
+
+ALTER TABLE contract_operations ADD COLUMN issuer TEXT NOT NULL DEFAULT 'default';
+
+-- each issuer has its own contract, hence its own VC ids: the primary key changes, the table is rebuilt
+CREATE TABLE vc_id_reservations_v2 (
+    issuer              TEXT NOT NULL,
+    vc_id               BIGINT NOT NULL,
+    job_id              TEXT NOT NULL UNIQUE,
+    reserved_at         INTEGER NOT NULL,
+    PRIMARY KEY (issuer, vc_id)
+);
+INSERT INTO vc_id_reservations_v2(issuer, vc_id, job_id, reserved_at)
+SELECT 'default', vc_id, job_id, reserved_at FROM vc_id_reservations;
+DROP TABLE vc_id_reservations;
+ALTER TABLE vc_id_reservations_v2 RENAME TO vc_id_reservations;
\ No newline at end of file
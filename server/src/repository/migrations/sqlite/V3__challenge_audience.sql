@@ -0,0 +1,18 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- A holder may have a pending challenge with every issuer of the deployment: the primary key changes, the table is rebuilt
+CREATE TABLE holders_challenges_v3 (
+    did_holder          TEXT NOT NULL,
+    challenge           TEXT NOT NULL,
+    expiration          INTEGER NOT NULL,
+    purpose             TEXT NOT NULL,
+    audience            TEXT NOT NULL,
+    PRIMARY KEY (did_holder, purpose, audience)
+);
+INSERT INTO holders_challenges_v3(did_holder, challenge, expiration, purpose, audience)
+SELECT did_holder, challenge, expiration, purpose, audience FROM holders_challenges;
+DROP TABLE holders_challenges;
+ALTER TABLE holders_challenges_v3 RENAME TO holders_challenges;
+CREATE INDEX IF NOT EXISTS holders_challenges_expiration_idx ON holders_challenges(expiration);
\ No newline at end of file
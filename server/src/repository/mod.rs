@@ -2,6 +2,11 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod database;
 pub mod postgres_repo;
+pub mod sqlite_repo;
+pub mod sqlite_operations;
 pub mod operations;
-pub mod models;
\ No newline at end of file
+pub mod models;
+pub mod migrations;
+pub mod challenge_store;
\ No newline at end of file
@@ -2,6 +2,8 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::time::SystemTime;
+
 use serde::{Deserialize, Serialize};
 use tokio_pg_mapper_derive::PostgresMapper;
 
@@ -10,6 +12,8 @@ use tokio_pg_mapper_derive::PostgresMapper;
 pub struct IssuerIdentity {
     pub did: String,
     pub fragment: String,
+    /// Issuer the identity belongs to, see [`crate::utils::issuers::IssuerRegistry`]
+    pub name: String,
 }
 
 #[derive(Deserialize, PostgresMapper, Serialize, Clone, Debug)]
@@ -17,5 +21,231 @@ pub struct IssuerIdentity {
 pub struct HolderChallenge {
     pub did_holder: String,
     pub challenge: String,
-    pub expiration: String,
-}
\ No newline at end of file
+    pub expiration: SystemTime,
+    /// Flow the challenge was requested for, see [`ChallengePurpose`]
+    pub purpose: String,
+    /// Service expected to verify the answer, the issuer URL
+    pub audience: String,
+}
+
+/// Flow a challenge can be answered in, a nonce is accepted only by its own flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChallengePurpose {
+    /// Credential request (POST /credentials)
+    #[default]
+    Issue,
+    /// Verifiable presentation authorizing a revocation
+    Revoke,
+    /// Renewal of an issued credential
+    Renew,
+    /// Login to the admin endpoints
+    AdminLogin,
+}
+
+impl ChallengePurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChallengePurpose::Issue => "issue",
+            ChallengePurpose::Revoke => "revoke",
+            ChallengePurpose::Renew => "renew",
+            ChallengePurpose::AdminLogin => "admin-login",
+        }
+    }
+}
+
+impl std::str::FromStr for ChallengePurpose {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "issue" => Ok(ChallengePurpose::Issue),
+            "revoke" => Ok(ChallengePurpose::Revoke),
+            "renew" => Ok(ChallengePurpose::Renew),
+            "admin-login" => Ok(ChallengePurpose::AdminLogin),
+            other => Err(format!("Unknown challenge purpose: {}", other)),
+        }
+    }
+}
+#[derive(Deserialize, PostgresMapper, Serialize, Clone, Debug)]
+#[pg_mapper(table = "issuance_jobs")] 
+pub struct IssuanceJob {
+    pub id: String,
+    pub did_holder: String,
+    pub credential_id: String,
+    pub credential_jwt: String,
+    pub wallet_signature: String,
+    /// Holder account bound by the wallet signature
+    pub wallet_address: String,
+    /// Challenge passed to addUser, see [`crate::utils::wallet_binding::VerifiedBinding`]
+    pub challenge: String,
+    /// Scheme of the wallet signature, e.g. eip712
+    pub signature_scheme: String,
+    pub issuance_date: i64,
+    pub expiration_date: i64,
+    pub status: String,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    /// Issuer that signed the credential and owns the contract it is registered on
+    pub issuer: String,
+}
+
+/// Lifecycle of an issuance job or of a contract operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Intended, transaction not sent yet
+    Pending,
+    /// Transaction sent, waiting for the receipt
+    Submitted,
+    /// Expected event found (e.g. VC_added), for jobs the credential can be released
+    Confirmed,
+    /// Transaction reverted or never sent
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Pending => "pending",
+            JobStatus::Submitted => "submitted",
+            JobStatus::Confirmed => "confirmed",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for JobStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(JobStatus::Pending),
+            "submitted" => Ok(JobStatus::Submitted),
+            "confirmed" => Ok(JobStatus::Confirmed),
+            "failed" => Ok(JobStatus::Failed),
+            other => Err(format!("Unknown job status: {}", other)),
+        }
+    }
+}
+
+/// Outbox entry of a write towards the Identity smart contract
+#[derive(Deserialize, PostgresMapper, Serialize, Clone, Debug)]
+#[pg_mapper(table = "contract_operations")] 
+pub struct ContractOperation {
+    pub id: String,
+    pub kind: String,
+    pub credential_id: String,
+    pub job_id: Option<String>,
+    pub status: String,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub alerted: bool,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    /// Issuer whose Identity contract is written
+    pub issuer: String,
+}
+
+impl ContractOperation {
+    pub fn new(kind: OperationKind, issuer: String, credential_id: String, job_id: Option<String>) -> Self {
+        let now = SystemTime::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: kind.as_str().to_owned(),
+            credential_id,
+            job_id,
+            status: JobStatus::Pending.as_str().to_owned(),
+            tx_hash: None,
+            error: None,
+            attempts: 0,
+            alerted: false,
+            created_at: now,
+            updated_at: now,
+            issuer,
+        }
+    }
+}
+
+/// Identity smart contract writes tracked by the outbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationKind {
+    AddUser,
+    RevokeVc,
+}
+
+impl OperationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperationKind::AddUser => "add_user",
+            OperationKind::RevokeVc => "revoke_vc",
+        }
+    }
+}
+
+impl std::str::FromStr for OperationKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "add_user" => Ok(OperationKind::AddUser),
+            "revoke_vc" => Ok(OperationKind::RevokeVc),
+            other => Err(format!("Unknown operation kind: {}", other)),
+        }
+    }
+}
+
+/// Log emitted by the Identity smart contract, as stored by the event indexer
+#[derive(Deserialize, PostgresMapper, Serialize, Clone, Debug)]
+#[pg_mapper(table = "contract_events")] 
+pub struct ContractEvent {
+    pub contract: String,
+    pub block_number: i64,
+    pub block_hash: String,
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub event: String,
+    pub vc_id: Option<String>,
+    pub account: Option<String>,
+    pub data: String,
+}
+
+/// Last block processed by the event indexer for a contract
+#[derive(Deserialize, PostgresMapper, Serialize, Clone, Debug)]
+#[pg_mapper(table = "indexer_cursors")] 
+pub struct IndexerCursor {
+    pub contract: String,
+    pub block_number: i64,
+    pub block_hash: String,
+}
+
+/// VC id reserved for an issuance job before the credential is signed
+#[derive(Deserialize, PostgresMapper, Serialize, Clone, Debug)]
+#[pg_mapper(table = "vc_id_reservations")] 
+pub struct VcIdReservation {
+    pub vc_id: i64,
+    pub job_id: String,
+    pub reserved_at: SystemTime,
+    pub issuer: String,
+}
+
+/// Two-step transfer of the Identity smart contract ownership.
+/// Proposed by the current owner, accepted with a signature of the new owner.
+/// The status follows [`JobStatus`], with `pending` meaning proposed and not accepted yet.
+#[derive(Deserialize, PostgresMapper, Serialize, Clone, Debug)]
+#[pg_mapper(table = "ownership_transfers")] 
+pub struct OwnershipTransfer {
+    pub id: String,
+    pub contract: String,
+    pub current_owner: String,
+    pub new_owner: String,
+    pub challenge: String,
+    pub status: String,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
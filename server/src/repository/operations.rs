@@ -2,42 +2,92 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::time::SystemTime;
+
 use anyhow::anyhow;
 use async_trait::async_trait;
 use deadpool_postgres::Client as PostgresClient;
-use identity_iota::core::Timestamp;
 use tokio_pg_mapper::FromTokioPostgresRow;
 
 use crate::{repository::models::IssuerIdentity, errors::IssuerError};
 
-use super::models::HolderChallenge;
+use super::models::{ChallengePurpose, ContractEvent, ContractOperation, HolderChallenge, IndexerCursor, IssuanceJob, JobStatus, OwnershipTransfer, VcIdReservation};
 
 
 #[async_trait]
 pub trait IssuerIdentityExt {
-    async fn get_identity_did(&self) -> Result<IssuerIdentity, IssuerError>;
+    async fn get_identity_did(&self, name: &str) -> Result<IssuerIdentity, IssuerError>;
     async fn insert_identity_issuer(&self, identity: &IssuerIdentity) -> Result<IssuerIdentity, IssuerError>;
 }
 
 #[async_trait]
 pub trait HoldersChallengesExt {
-    async fn get_challenge(&self, did: &String, nonce: &String) -> Result<HolderChallenge, IssuerError>;
+    /// Deletes and returns the challenge in a single statement, so it can be used only once
+    async fn consume_challenge(&self, did: &str, nonce: &str, purpose: ChallengePurpose, audience: &str) -> Result<HolderChallenge, IssuerError>;
     async fn insert_challenge(&self, holder_challenge: &HolderChallenge) -> Result<HolderChallenge, IssuerError>;
-    async fn remove_challenge(&self, did: &String) ->  Result<(), IssuerError>;
-    async fn cleanup_challenges(&self) -> Result<(), anyhow::Error>;
+    /// Deletes the expired challenges, returns how many were removed
+    async fn cleanup_challenges(&self) -> Result<u64, anyhow::Error>;
+}
+
+#[async_trait]
+pub trait IssuanceJobsExt {
+    async fn insert_issuance_job(&self, job: &IssuanceJob) -> Result<IssuanceJob, IssuerError>;
+    async fn get_issuance_job(&self, id: &str) -> Result<IssuanceJob, IssuerError>;
+    async fn get_issuance_jobs_by_status(&self, status: JobStatus) -> Result<Vec<IssuanceJob>, IssuerError>;
+    async fn update_issuance_job(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError>;
+    /// Deletes the confirmed and failed jobs last updated before `before`, returns how many were removed
+    async fn prune_issuance_jobs(&self, before: SystemTime) -> Result<u64, IssuerError>;
+}
+
+#[async_trait]
+pub trait ContractOperationsExt {
+    async fn insert_operation(&self, operation: &ContractOperation) -> Result<ContractOperation, IssuerError>;
+    async fn get_operations_by_status(&self, status: JobStatus) -> Result<Vec<ContractOperation>, IssuerError>;
+    async fn get_latest_operation_by_job(&self, job_id: &str) -> Result<ContractOperation, IssuerError>;
+    async fn update_operation(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError>;
+    async fn resubmit_operation(&self, id: &str, tx_hash: &str) -> Result<(), IssuerError>;
+    async fn mark_operation_alerted(&self, id: &str) -> Result<(), IssuerError>;
+    /// Deletes the confirmed and failed operations last updated before `before`, returns how many were removed
+    async fn prune_operations(&self, before: SystemTime) -> Result<u64, IssuerError>;
+}
+
+#[async_trait]
+pub trait ContractEventsExt {
+    async fn insert_event(&self, event: &ContractEvent) -> Result<(), IssuerError>;
+    async fn remove_events_from(&self, contract: &str, block_number: i64) -> Result<(), IssuerError>;
+    async fn get_events_by_vc_id(&self, contract: &str, vc_id: &str) -> Result<Vec<ContractEvent>, IssuerError>;
+    async fn get_event_block_before(&self, contract: &str, block_number: i64) -> Result<IndexerCursor, IssuerError>;
+    async fn get_indexer_cursor(&self, contract: &str) -> Result<IndexerCursor, IssuerError>;
+    async fn set_indexer_cursor(&self, cursor: &IndexerCursor) -> Result<(), IssuerError>;
+}
+
+#[async_trait]
+pub trait VcIdReservationsExt {
+    async fn reserve_vc_id(&self, issuer: &str, first_free_id: i64, job_id: &str) -> Result<VcIdReservation, IssuerError>;
+    async fn release_vc_id(&self, job_id: &str) -> Result<(), IssuerError>;
+    async fn prune_vc_id_reservations(&self, issuer: &str, first_free_id: i64) -> Result<u64, IssuerError>;
+}
+
+#[async_trait]
+pub trait OwnershipTransfersExt {
+    async fn insert_ownership_transfer(&self, transfer: &OwnershipTransfer) -> Result<OwnershipTransfer, IssuerError>;
+    async fn get_latest_ownership_transfer(&self, contract: &str, status: JobStatus) -> Result<OwnershipTransfer, IssuerError>;
+    async fn update_ownership_transfer(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError>;
+    /// Deletes the completed and failed transfers last updated before `before`, returns how many were removed
+    async fn prune_ownership_transfers(&self, before: SystemTime) -> Result<u64, IssuerError>;
 }
 
 #[async_trait]
 impl IssuerIdentityExt for PostgresClient {
-    async fn get_identity_did(&self) -> Result<IssuerIdentity, IssuerError> {
+    async fn get_identity_did(&self, name: &str) -> Result<IssuerIdentity, IssuerError> {
         let stmt = include_str!("./sql/identities_get.sql"); //TODO: folder as env variable
         let stmt = stmt.replace("$table_fields", &IssuerIdentity::sql_table_fields());
         let stmt = self.prepare(&stmt).await?;
     
         match self
-        .query_one(&stmt, &[])
+        .query_one(&stmt, &[&name])
         .await{
-            Ok(row) => IssuerIdentity::from_row_ref(&row).map_err(|e| IssuerError::from(e)),
+            Ok(row) => IssuerIdentity::from_row_ref(&row).map_err(IssuerError::from),
             Err(_) =>  Err(IssuerError::RowNotFound),
         }
     
@@ -53,6 +103,7 @@ impl IssuerIdentityExt for PostgresClient {
             &[
                 &identity.did,
                 &identity.fragment,
+                &identity.name,
             ],
         )
         .await?
@@ -68,22 +119,30 @@ impl IssuerIdentityExt for PostgresClient {
 #[async_trait]
 impl HoldersChallengesExt for PostgresClient {
 
-    async fn get_challenge(&self, did: &String, nonce: &String) -> Result<HolderChallenge, IssuerError> {
+    async fn consume_challenge(&self, did: &str, nonce: &str, purpose: ChallengePurpose, audience: &str) -> Result<HolderChallenge, IssuerError> {
 
-        let _stmt = include_str!("./sql/holders_challenges_get.sql");
+        let _stmt = include_str!("./sql/holders_challenges_consume.sql");
         let _stmt = _stmt.replace("$table_fields", &HolderChallenge::sql_table_fields());
         let stmt = self.prepare(&_stmt).await?;
 
         match self
-        .query_one(&stmt, &[did, nonce])
-        .await{
-            Ok(row) => HolderChallenge::from_row_ref(&row).map_err(|e| IssuerError::from(e)),
-            Err(_) =>  Err(IssuerError::RowNotFound),
+        .query_opt(&stmt, &[&did, &nonce, &purpose.as_str(), &audience])
+        .await? {
+            Some(row) => HolderChallenge::from_row_ref(&row).map_err(IssuerError::from),
+            None => {
+                log::warn!("Rejected unknown or already used {} challenge of {}", purpose.as_str(), did);
+                Err(IssuerError::NonExistingRequestError)
+            }
         }
     
     }
 
     async fn insert_challenge(&self, holder_challenge: &HolderChallenge) -> Result<HolderChallenge, IssuerError>{
+        // an expired challenge does not count as pending
+        let _stmt = include_str!("./sql/holders_challenges_remove_expired.sql");
+        let stmt = self.prepare(_stmt).await?;
+        self.query(&stmt, &[&holder_challenge.did_holder, &SystemTime::now()]).await?;
+
         let _stmt = include_str!("./sql/holders_challenges_insert.sql");
         let _stmt = _stmt.replace("$table_fields", &HolderChallenge::sql_table_fields());
         let stmt = self.prepare(&_stmt).await?;
@@ -93,7 +152,9 @@ impl HoldersChallengesExt for PostgresClient {
             &[
                 &holder_challenge.did_holder,
                 &holder_challenge.expiration,
-                &holder_challenge.challenge
+                &holder_challenge.challenge,
+                &holder_challenge.purpose,
+                &holder_challenge.audience
             ],
         )
         .await?
@@ -101,24 +162,350 @@ impl HoldersChallengesExt for PostgresClient {
         .map(|row| HolderChallenge::from_row_ref(row).unwrap())
         .collect::<Vec<HolderChallenge>>()
         .pop()
-        .ok_or(IssuerError::RowNotFound) // more applicable for SELECTs
+        .ok_or(IssuerError::ChallengePendingError) // the holder already has a pending challenge
+    }
+
+    async fn cleanup_challenges(&self) -> Result<u64, anyhow::Error> {
+        let _stmt = include_str!("./sql/holders_challenges_cleanup.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.execute(&stmt, &[&SystemTime::now()]).await
+            .map_err(|e| anyhow!("SQL Query delete failed: {}", e))
+    }
+}
+
+#[async_trait]
+impl IssuanceJobsExt for PostgresClient {
+
+    async fn insert_issuance_job(&self, job: &IssuanceJob) -> Result<IssuanceJob, IssuerError> {
+        let _stmt = include_str!("./sql/issuance_jobs_insert.sql");
+        let _stmt = _stmt.replace("$table_fields", &IssuanceJob::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        self.query(
+            &stmt,
+            &[
+                &job.id,
+                &job.did_holder,
+                &job.credential_id,
+                &job.credential_jwt,
+                &job.wallet_signature,
+                &job.wallet_address,
+                &job.challenge,
+                &job.signature_scheme,
+                &job.issuance_date,
+                &job.expiration_date,
+                &job.status,
+                &job.created_at,
+                &job.issuer,
+            ],
+        )
+        .await?
+        .iter()
+        .map(|row| IssuanceJob::from_row_ref(row).unwrap())
+        .collect::<Vec<IssuanceJob>>()
+        .pop()
+        .ok_or(IssuerError::RowNotFound)
+    }
+
+    async fn get_issuance_job(&self, id: &str) -> Result<IssuanceJob, IssuerError> {
+        let _stmt = include_str!("./sql/issuance_jobs_get.sql");
+        let _stmt = _stmt.replace("$table_fields", &IssuanceJob::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        match self
+        .query_one(&stmt, &[&id])
+        .await{
+            Ok(row) => IssuanceJob::from_row_ref(&row).map_err(IssuerError::from),
+            Err(_) =>  Err(IssuerError::RowNotFound),
+        }
     }
 
-    async fn remove_challenge(&self, did: &String) ->  Result<(), IssuerError> {
-        let _stmt = include_str!("./sql/holders_challenges_remove.sql");
+    async fn get_issuance_jobs_by_status(&self, status: JobStatus) -> Result<Vec<IssuanceJob>, IssuerError> {
+        let _stmt = include_str!("./sql/issuance_jobs_get_by_status.sql");
+        let _stmt = _stmt.replace("$table_fields", &IssuanceJob::sql_table_fields());
         let stmt = self.prepare(&_stmt).await?;
 
-        self.query(&stmt, &[did]).await?;
+        self.query(&stmt, &[&status.as_str()])
+        .await?
+        .iter()
+        .map(|row| IssuanceJob::from_row_ref(row).map_err(IssuerError::from))
+        .collect()
+    }
+
+    async fn update_issuance_job(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/issuance_jobs_update.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.query(&stmt, &[&id, &status.as_str(), &tx_hash, &error, &SystemTime::now()]).await?;
         Ok(())
     }
 
-    async fn cleanup_challenges(&self) -> Result<(), anyhow::Error> {
-        let _stmt = include_str!("./sql/holders_challenges_cleanup.sql");
+    async fn prune_issuance_jobs(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        let _stmt = include_str!("./sql/issuance_jobs_prune.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        let pruned: i64 = self.query_one(&stmt, &[&before]).await?.get(0);
+        Ok(pruned as u64)
+    }
+}
+
+
+#[async_trait]
+impl ContractOperationsExt for PostgresClient {
+
+    async fn insert_operation(&self, operation: &ContractOperation) -> Result<ContractOperation, IssuerError> {
+        let _stmt = include_str!("./sql/contract_operations_insert.sql");
+        let _stmt = _stmt.replace("$table_fields", &ContractOperation::sql_table_fields());
         let stmt = self.prepare(&_stmt).await?;
 
-        self.query(&stmt, &[&Timestamp::now_utc().to_rfc3339()]).await
-            .map_err(|e| anyhow!("SQL Query delete failed: {}", e.to_string()))?;
+        self.query(
+            &stmt,
+            &[
+                &operation.id,
+                &operation.kind,
+                &operation.credential_id,
+                &operation.job_id,
+                &operation.status,
+                &operation.created_at,
+                &operation.issuer,
+            ],
+        )
+        .await?
+        .iter()
+        .map(|row| ContractOperation::from_row_ref(row).unwrap())
+        .collect::<Vec<ContractOperation>>()
+        .pop()
+        .ok_or(IssuerError::RowNotFound)
+    }
+
+    async fn get_operations_by_status(&self, status: JobStatus) -> Result<Vec<ContractOperation>, IssuerError> {
+        let _stmt = include_str!("./sql/contract_operations_get_by_status.sql");
+        let _stmt = _stmt.replace("$table_fields", &ContractOperation::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        self.query(&stmt, &[&status.as_str()])
+        .await?
+        .iter()
+        .map(|row| ContractOperation::from_row_ref(row).map_err(IssuerError::from))
+        .collect()
+    }
+
+    async fn get_latest_operation_by_job(&self, job_id: &str) -> Result<ContractOperation, IssuerError> {
+        let _stmt = include_str!("./sql/contract_operations_get_latest_by_job.sql");
+        let _stmt = _stmt.replace("$table_fields", &ContractOperation::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        match self.query_opt(&stmt, &[&job_id]).await? {
+            Some(row) => ContractOperation::from_row_ref(&row).map_err(IssuerError::from),
+            None => Err(IssuerError::RowNotFound),
+        }
+    }
+
+    async fn update_operation(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/contract_operations_update.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.query(&stmt, &[&id, &status.as_str(), &tx_hash, &error, &SystemTime::now()]).await?;
+        Ok(())
+    }
+
+    async fn resubmit_operation(&self, id: &str, tx_hash: &str) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/contract_operations_resubmit.sql");
+        let stmt = self.prepare(_stmt).await?;
 
+        self.query(&stmt, &[&id, &tx_hash, &SystemTime::now()]).await?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn mark_operation_alerted(&self, id: &str) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/contract_operations_mark_alerted.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.query(&stmt, &[&id]).await?;
+        Ok(())
+    }
+
+    async fn prune_operations(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        let _stmt = include_str!("./sql/contract_operations_prune.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        Ok(self.execute(&stmt, &[&before]).await?)
+    }
+}
+
+
+#[async_trait]
+impl ContractEventsExt for PostgresClient {
+
+    async fn insert_event(&self, event: &ContractEvent) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/contract_events_insert.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.query(
+            &stmt,
+            &[
+                &event.contract,
+                &event.block_number,
+                &event.block_hash,
+                &event.tx_hash,
+                &event.log_index,
+                &event.event,
+                &event.vc_id,
+                &event.account,
+                &event.data,
+            ],
+        ).await?;
+        Ok(())
+    }
+
+    async fn remove_events_from(&self, contract: &str, block_number: i64) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/contract_events_remove_from.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.query(&stmt, &[&contract, &block_number]).await?;
+        Ok(())
+    }
+
+    async fn get_events_by_vc_id(&self, contract: &str, vc_id: &str) -> Result<Vec<ContractEvent>, IssuerError> {
+        let _stmt = include_str!("./sql/contract_events_get_by_vc_id.sql");
+        let _stmt = _stmt.replace("$table_fields", &ContractEvent::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        self.query(&stmt, &[&contract, &vc_id])
+        .await?
+        .iter()
+        .map(|row| ContractEvent::from_row_ref(row).map_err(IssuerError::from))
+        .collect()
+    }
+
+    async fn get_event_block_before(&self, contract: &str, block_number: i64) -> Result<IndexerCursor, IssuerError> {
+        let stmt = self.prepare(include_str!("./sql/contract_events_get_block_before.sql")).await?;
+
+        match self.query_opt(&stmt, &[&contract, &block_number]).await? {
+            Some(row) => IndexerCursor::from_row_ref(&row).map_err(IssuerError::from),
+            None => Err(IssuerError::RowNotFound),
+        }
+    }
+
+    async fn get_indexer_cursor(&self, contract: &str) -> Result<IndexerCursor, IssuerError> {
+        let _stmt = include_str!("./sql/indexer_cursors_get.sql");
+        let _stmt = _stmt.replace("$table_fields", &IndexerCursor::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        match self
+        .query_one(&stmt, &[&contract])
+        .await{
+            Ok(row) => IndexerCursor::from_row_ref(&row).map_err(IssuerError::from),
+            Err(_) =>  Err(IssuerError::RowNotFound),
+        }
+    }
+
+    async fn set_indexer_cursor(&self, cursor: &IndexerCursor) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/indexer_cursors_upsert.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.query(&stmt, &[&cursor.contract, &cursor.block_number, &cursor.block_hash]).await?;
+        Ok(())
+    }
+}
+
+
+/// Concurrent reservations racing for the same id are retried this many times
+const VC_ID_RESERVATION_ATTEMPTS: usize = 5;
+
+#[async_trait]
+impl VcIdReservationsExt for PostgresClient {
+
+    async fn reserve_vc_id(&self, issuer: &str, first_free_id: i64, job_id: &str) -> Result<VcIdReservation, IssuerError> {
+        let _stmt = include_str!("./sql/vc_id_reservations_reserve.sql");
+        let _stmt = _stmt.replace("$table_fields", &VcIdReservation::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        for _ in 0..VC_ID_RESERVATION_ATTEMPTS {
+            let reservation = self.query(&stmt, &[&first_free_id, &job_id, &SystemTime::now(), &issuer])
+            .await?
+            .iter()
+            .map(|row| VcIdReservation::from_row_ref(row).unwrap())
+            .collect::<Vec<VcIdReservation>>()
+            .pop();
+            // no row means that another request took the same id in the meantime
+            if let Some(reservation) = reservation {
+                return Ok(reservation);
+            }
+        }
+        Err(IssuerError::OtherError("VC id reservation failed, too many concurrent requests".to_owned()))
+    }
+
+    async fn release_vc_id(&self, job_id: &str) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/vc_id_reservations_release.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.query(&stmt, &[&job_id]).await?;
+        Ok(())
+    }
+
+    async fn prune_vc_id_reservations(&self, issuer: &str, first_free_id: i64) -> Result<u64, IssuerError> {
+        let stmt = self.prepare(include_str!("./sql/vc_id_reservations_prune.sql")).await?;
+
+        Ok(self.execute(&stmt, &[&issuer, &first_free_id]).await?)
+    }
+}
+
+
+#[async_trait]
+impl OwnershipTransfersExt for PostgresClient {
+
+    async fn insert_ownership_transfer(&self, transfer: &OwnershipTransfer) -> Result<OwnershipTransfer, IssuerError> {
+        let _stmt = include_str!("./sql/ownership_transfers_insert.sql");
+        let _stmt = _stmt.replace("$table_fields", &OwnershipTransfer::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        self.query(
+            &stmt,
+            &[
+                &transfer.id,
+                &transfer.contract,
+                &transfer.current_owner,
+                &transfer.new_owner,
+                &transfer.challenge,
+                &transfer.status,
+                &transfer.created_at,
+            ],
+        )
+        .await?
+        .iter()
+        .map(|row| OwnershipTransfer::from_row_ref(row).unwrap())
+        .collect::<Vec<OwnershipTransfer>>()
+        .pop()
+        .ok_or(IssuerError::RowNotFound)
+    }
+
+    async fn get_latest_ownership_transfer(&self, contract: &str, status: JobStatus) -> Result<OwnershipTransfer, IssuerError> {
+        let _stmt = include_str!("./sql/ownership_transfers_get_latest.sql");
+        let _stmt = _stmt.replace("$table_fields", &OwnershipTransfer::sql_table_fields());
+        let stmt = self.prepare(&_stmt).await?;
+
+        match self
+        .query_one(&stmt, &[&contract, &status.as_str()])
+        .await{
+            Ok(row) => OwnershipTransfer::from_row_ref(&row).map_err(IssuerError::from),
+            Err(_) =>  Err(IssuerError::RowNotFound),
+        }
+    }
+
+    async fn update_ownership_transfer(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        let _stmt = include_str!("./sql/ownership_transfers_update.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        self.query(&stmt, &[&id, &status.as_str(), &tx_hash, &error, &SystemTime::now()]).await?;
+        Ok(())
+    }
+
+    async fn prune_ownership_transfers(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        let _stmt = include_str!("./sql/ownership_transfers_prune.sql");
+        let stmt = self.prepare(_stmt).await?;
+
+        Ok(self.execute(&stmt, &[&before]).await?)
+    }
+}
@@ -2,55 +2,123 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-use deadpool_postgres::{ManagerConfig, Pool, RecyclingMethod};
+use clap::ValueEnum;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
+use native_tls::{Certificate, Identity, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use tokio_postgres::config::SslMode;
 use tokio_postgres::NoTls;
 
 use crate::utils::configs::DatabaseConfig;
 
-use super::operations::HoldersChallengesExt;
-
-/// Clean challenges from the database every hour
-async fn cleanup_loop(pool: Pool)
-{   
-    let mut interval = tokio::time::interval(Duration::from_secs(3600));
-    if let Ok(client) = pool.get().await {
-        loop
-        {
-            // cleanup loop
-            interval.tick().await;
-            let result = client.cleanup_challenges().await;
-            match result {
-                Ok(_) => log::info!("SQL cleanup completed"),
-                Err(err) => log::error!("SQL cleanup error: {}", err.to_string())
-            }
-        }       
-    }
-    else {
-        log::error!("Cannot access DB pool. Cleanup disabled");
-    }
+/// TLS of the Postgres connections, same meaning as the libpq sslmode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DbSslMode {
+    /// Plain connections
+    Disable,
+    /// TLS when the server supports it, certificate not verified
+    Prefer,
+    /// TLS required, certificate not verified
+    Require,
+    /// TLS required, certificate verified against the CA but not against the host name
+    VerifyCa,
+    /// TLS required, certificate and host name verified
+    VerifyFull,
+}
 
+impl DbSslMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DbSslMode::Disable => "disable",
+            DbSslMode::Prefer => "prefer",
+            DbSslMode::Require => "require",
+            DbSslMode::VerifyCa => "verify-ca",
+            DbSslMode::VerifyFull => "verify-full",
+        }
+    }
 }
 
 pub async fn init(configuration: DatabaseConfig) -> Result<Pool> {
     log::info!("init database");
 
-    let mut config = deadpool_postgres::Config::new();
-    config.user = Some(configuration.db_user);
-    config.password = Some(configuration.db_password.value());
-    config.dbname = Some(configuration.db_name);
-    config.host = Some(configuration.db_host);
-    config.port = Some(configuration.db_port);
+    let mut config = match &configuration.database_url {
+        Some(url) => tokio_postgres::Config::from_str(&url.value())?,
+        None => {
+            let mut config = tokio_postgres::Config::new();
+            config
+                .user(configuration.db_user.as_ref().ok_or(anyhow!("DB_USER is required"))?)
+                .password(configuration.db_password.as_ref().ok_or(anyhow!("DB_PASSWORD is required"))?.value())
+                .dbname(configuration.db_name.as_ref().ok_or(anyhow!("DB_NAME is required"))?)
+                .host(configuration.db_host.as_ref().ok_or(anyhow!("DB_HOST is required"))?)
+                .port(configuration.db_port.ok_or(anyhow!("DB_PORT is required"))?);
+            config
+        }
+    };
+    config.connect_timeout(Duration::from_secs(configuration.db_connect_timeout));
 
-    config.manager = Some(ManagerConfig {
+    // an explicit DB_SSL_MODE wins over the sslmode of the url
+    let ssl_mode = match (configuration.db_ssl_mode, &configuration.database_url) {
+        (Some(ssl_mode), _) => ssl_mode,
+        (None, Some(_)) => match config.get_ssl_mode() {
+            SslMode::Prefer => DbSslMode::Prefer,
+            SslMode::Require => DbSslMode::Require,
+            _ => DbSslMode::Disable,
+        },
+        (None, None) => DbSslMode::Disable,
+    };
+
+    let manager_config = ManagerConfig {
         recycling_method: RecyclingMethod::Fast,
-    });
-    let pool = config.create_pool(None, NoTls)?;
-    log::info!("pool database");
+    };
+    let manager = match ssl_mode {
+        DbSslMode::Disable => {
+            config.ssl_mode(SslMode::Disable);
+            Manager::from_config(config, NoTls, manager_config)
+        }
+        DbSslMode::Prefer => {
+            config.ssl_mode(SslMode::Prefer);
+            Manager::from_config(config, tls_connector(ssl_mode, &configuration)?, manager_config)
+        }
+        DbSslMode::Require | DbSslMode::VerifyCa | DbSslMode::VerifyFull => {
+            config.ssl_mode(SslMode::Require);
+            Manager::from_config(config, tls_connector(ssl_mode, &configuration)?, manager_config)
+        }
+    };
+
+    let pool = Pool::builder(manager)
+        .max_size(configuration.db_max_pool_size.into())
+        .wait_timeout(Some(Duration::from_secs(configuration.db_pool_wait_timeout)))
+        .create_timeout(Some(Duration::from_secs(configuration.db_connect_timeout)))
+        .runtime(Runtime::Tokio1)
+        .build()?;
+    log::info!("pool database, max size {}, ssl mode {}", configuration.db_max_pool_size, ssl_mode.as_str());
 
-    tokio::task::spawn(cleanup_loop(pool.clone()));
     Ok(pool)
 }
+
+/// TLS connector verifying the server as required by `ssl_mode`, with the optional CA and client certificate
+fn tls_connector(ssl_mode: DbSslMode, configuration: &DatabaseConfig) -> Result<MakeTlsConnector> {
+    let mut builder = TlsConnector::builder();
+    match ssl_mode {
+        DbSslMode::Prefer | DbSslMode::Require => { builder.danger_accept_invalid_certs(true); },
+        DbSslMode::VerifyCa => { builder.danger_accept_invalid_hostnames(true); },
+        DbSslMode::Disable | DbSslMode::VerifyFull => {},
+    }
+
+    if let Some(path) = &configuration.db_ssl_root_cert {
+        let pem = std::fs::read(path).map_err(|err| anyhow!("Cannot read DB_SSL_ROOT_CERT {}: {}", path.display(), err))?;
+        builder.add_root_certificate(Certificate::from_pem(&pem)?);
+    }
+    if let (Some(cert_path), Some(key_path)) = (&configuration.db_ssl_client_cert, &configuration.db_ssl_client_key) {
+        let cert = std::fs::read(cert_path).map_err(|err| anyhow!("Cannot read DB_SSL_CLIENT_CERT {}: {}", cert_path.display(), err))?;
+        let key = std::fs::read(key_path).map_err(|err| anyhow!("Cannot read DB_SSL_CLIENT_KEY {}: {}", key_path.display(), err))?;
+        builder.identity(Identity::from_pkcs8(&cert, &key)?);
+    }
+
+    Ok(MakeTlsConnector::new(builder.build()?))
+}
@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT $table_fields 
+FROM contract_events 
+WHERE contract=$1
+AND vc_id=$2
+ORDER BY block_number, log_index;
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+INSERT INTO contract_events(contract, block_number, block_hash, tx_hash, log_index, event, vc_id, account, data)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+ON CONFLICT (tx_hash, log_index) DO UPDATE
+SET block_number=EXCLUDED.block_number, block_hash=EXCLUDED.block_hash;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+DELETE FROM contract_events WHERE contract=$1 AND block_number >= $2;
\ No newline at end of file
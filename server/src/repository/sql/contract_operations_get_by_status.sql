@@ -3,6 +3,6 @@
 -- SPDX-License-Identifier: GPL-3.0-or-later
 
 SELECT $table_fields 
-FROM holders_challenges 
-WHERE did_holder=$1
-AND challenge=$2;
\ No newline at end of file
+FROM contract_operations 
+WHERE status=$1
+ORDER BY created_at;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT $table_fields 
+FROM contract_operations 
+WHERE job_id=$1
+ORDER BY created_at DESC
+LIMIT 1;
\ No newline at end of file
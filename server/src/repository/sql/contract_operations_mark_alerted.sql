@@ -0,0 +1,5 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+UPDATE contract_operations SET alerted=TRUE WHERE id=$1;
\ No newline at end of file
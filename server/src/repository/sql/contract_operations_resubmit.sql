@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+UPDATE contract_operations 
+SET status='submitted', tx_hash=$2, attempts=attempts+1, updated_at=$3
+WHERE id=$1;
\ No newline at end of file
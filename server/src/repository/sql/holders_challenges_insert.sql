@@ -2,6 +2,7 @@
 
 -- SPDX-License-Identifier: GPL-3.0-or-later
 
-INSERT INTO holders_challenges(did_holder, expiration, challenge)
-VALUES ($1, $2, $3)
+INSERT INTO holders_challenges(did_holder, expiration, challenge, purpose, audience)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (did_holder, purpose, audience) DO NOTHING
 RETURNING $table_fields;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+DELETE FROM holders_challenges WHERE did_holder=$1 AND expiration < $2;
\ No newline at end of file
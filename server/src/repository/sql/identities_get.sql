@@ -2,4 +2,4 @@
 
 -- SPDX-License-Identifier: GPL-3.0-or-later
 
-SELECT $table_fields FROM identities;
\ No newline at end of file
+SELECT $table_fields FROM identities WHERE name=$1;
\ No newline at end of file
@@ -2,6 +2,6 @@
 
 -- SPDX-License-Identifier: GPL-3.0-or-later
 
-INSERT INTO identities(did, fragment)
-VALUES ($1, $2)
+INSERT INTO identities(did, fragment, name)
+VALUES ($1, $2, $3)
 RETURNING $table_fields;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT $table_fields 
+FROM issuance_jobs 
+WHERE id=$1;
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT $table_fields 
+FROM issuance_jobs 
+WHERE status=$1
+ORDER BY created_at;
\ No newline at end of file
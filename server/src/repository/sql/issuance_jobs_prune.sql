@@ -0,0 +1,14 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- the audit trail in contract_operations outlives the jobs, references are detached
+WITH pruned AS (
+    DELETE FROM issuance_jobs
+    WHERE status IN ('confirmed', 'failed') AND updated_at < $1
+    RETURNING id
+), detached AS (
+    UPDATE contract_operations SET job_id = NULL
+    WHERE job_id IN (SELECT id FROM pruned)
+)
+SELECT COUNT(*) FROM pruned;
\ No newline at end of file
@@ -0,0 +1,10 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT $table_fields 
+FROM ownership_transfers 
+WHERE contract=$1
+AND status=$2
+ORDER BY created_at DESC
+LIMIT 1;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+DELETE FROM ownership_transfers
+WHERE status IN ('confirmed', 'failed') AND updated_at < $1;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT COALESCE(MAX(version), 0) FROM schema_migrations;
\ No newline at end of file
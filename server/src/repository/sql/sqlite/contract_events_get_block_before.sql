@@ -0,0 +1,10 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT contract, block_number, block_hash 
+FROM contract_events 
+WHERE contract=?1
+AND block_number<?2
+ORDER BY block_number DESC
+LIMIT 1;
\ No newline at end of file
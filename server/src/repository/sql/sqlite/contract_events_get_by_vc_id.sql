@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT contract, block_number, block_hash, tx_hash, log_index, event, vc_id, account, data 
+FROM contract_events 
+WHERE contract=?1
+AND vc_id=?2
+ORDER BY block_number, log_index;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+UPDATE contract_operations SET job_id = NULL
+WHERE job_id IN (
+    SELECT id FROM issuance_jobs
+    WHERE status IN ('confirmed', 'failed') AND updated_at < ?1
+);
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT id, kind, credential_id, job_id, status, tx_hash, error, attempts, alerted, created_at, updated_at, issuer 
+FROM contract_operations 
+WHERE status=?1
+ORDER BY created_at;
\ No newline at end of file
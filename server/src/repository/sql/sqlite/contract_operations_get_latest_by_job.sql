@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT id, kind, credential_id, job_id, status, tx_hash, error, attempts, alerted, created_at, updated_at, issuer 
+FROM contract_operations 
+WHERE job_id=?1
+ORDER BY created_at DESC
+LIMIT 1;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+INSERT INTO contract_operations(id, kind, credential_id, job_id, status, created_at, updated_at, issuer)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7)
+RETURNING id, kind, credential_id, job_id, status, tx_hash, error, attempts, alerted, created_at, updated_at, issuer;
\ No newline at end of file
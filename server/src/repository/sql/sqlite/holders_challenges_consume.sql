@@ -0,0 +1,10 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+DELETE FROM holders_challenges 
+WHERE did_holder=?1
+AND challenge=?2
+AND purpose=?3
+AND audience=?4
+RETURNING did_holder, challenge, expiration, purpose, audience;
\ No newline at end of file
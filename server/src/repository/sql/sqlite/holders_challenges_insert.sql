@@ -0,0 +1,8 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+INSERT INTO holders_challenges(did_holder, expiration, challenge, purpose, audience)
+VALUES (?1, ?2, ?3, ?4, ?5)
+ON CONFLICT (did_holder, purpose, audience) DO NOTHING
+RETURNING did_holder, challenge, expiration, purpose, audience;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+INSERT INTO identities(did, fragment, name)
+VALUES (?1, ?2, ?3)
+RETURNING did, fragment, name;
\ No newline at end of file
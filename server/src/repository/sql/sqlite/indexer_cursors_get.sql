@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT contract, block_number, block_hash 
+FROM indexer_cursors 
+WHERE contract=?1;
\ No newline at end of file
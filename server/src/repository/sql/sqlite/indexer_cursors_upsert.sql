@@ -0,0 +1,8 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+INSERT INTO indexer_cursors(contract, block_number, block_hash)
+VALUES (?1, ?2, ?3)
+ON CONFLICT (contract) DO UPDATE
+SET block_number=excluded.block_number, block_hash=excluded.block_hash;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT id, did_holder, credential_id, credential_jwt, wallet_signature, wallet_address, challenge, signature_scheme, issuance_date, expiration_date, status, tx_hash, error, created_at, updated_at, issuer 
+FROM issuance_jobs 
+WHERE id=?1;
\ No newline at end of file
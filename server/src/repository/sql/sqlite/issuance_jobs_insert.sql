@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+INSERT INTO issuance_jobs(id, did_holder, credential_id, credential_jwt, wallet_signature, wallet_address, challenge, signature_scheme, issuance_date, expiration_date, status, created_at, updated_at, issuer)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?12, ?13)
+RETURNING id, did_holder, credential_id, credential_jwt, wallet_signature, wallet_address, challenge, signature_scheme, issuance_date, expiration_date, status, tx_hash, error, created_at, updated_at, issuer;
\ No newline at end of file
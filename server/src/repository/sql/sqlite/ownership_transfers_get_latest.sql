@@ -0,0 +1,10 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+SELECT id, contract, current_owner, new_owner, challenge, status, tx_hash, error, created_at, updated_at 
+FROM ownership_transfers 
+WHERE contract=?1
+AND status=?2
+ORDER BY created_at DESC
+LIMIT 1;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+INSERT INTO ownership_transfers(id, contract, current_owner, new_owner, challenge, status, created_at, updated_at)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)
+RETURNING id, contract, current_owner, new_owner, challenge, status, tx_hash, error, created_at, updated_at;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+UPDATE ownership_transfers 
+SET status=?2, tx_hash=?3, error=?4, updated_at=?5
+WHERE id=?1;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+INSERT INTO schema_migrations(version, name, applied_at)
+VALUES (?1, ?2, ?3);
\ No newline at end of file
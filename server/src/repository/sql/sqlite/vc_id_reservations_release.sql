@@ -2,4 +2,4 @@
 
 -- SPDX-License-Identifier: GPL-3.0-or-later
 
-DELETE FROM holders_challenges WHERE did_holder=$1;
\ No newline at end of file
+DELETE FROM vc_id_reservations WHERE job_id=?1;
\ No newline at end of file
@@ -0,0 +1,16 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- smallest id not reserved yet by the issuer, starting from the first free id on its contract:
+-- either the first free id itself or the one following a reservation
+INSERT INTO vc_id_reservations(issuer, vc_id, job_id, reserved_at)
+SELECT ?4, MIN(candidate), ?2, ?3
+FROM (
+    SELECT ?1 AS candidate
+    UNION ALL
+    SELECT vc_id + 1 FROM vc_id_reservations WHERE issuer=?4 AND vc_id >= ?1
+)
+WHERE candidate NOT IN (SELECT vc_id FROM vc_id_reservations WHERE issuer=?4 AND vc_id >= ?1)
+ON CONFLICT (issuer, vc_id) DO NOTHING
+RETURNING vc_id, job_id, reserved_at, issuer;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+DELETE FROM vc_id_reservations WHERE issuer=$1 AND vc_id<$2;
\ No newline at end of file
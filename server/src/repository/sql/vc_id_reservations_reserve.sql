@@ -0,0 +1,12 @@
+-- SPDX-FileCopyrightText: 2024 Fondazione LINKS
+
+-- SPDX-License-Identifier: GPL-3.0-or-later
+
+-- smallest id not reserved yet by the issuer, starting from the first free id on its contract,
+-- the reservations below it are never candidates again
+INSERT INTO vc_id_reservations(issuer, vc_id, job_id, reserved_at)
+SELECT $4, MIN(candidate), $2, $3
+FROM generate_series($1::BIGINT, $1::BIGINT + (SELECT COUNT(*) FROM vc_id_reservations WHERE issuer=$4 AND vc_id>=$1)) AS candidate
+WHERE candidate NOT IN (SELECT vc_id FROM vc_id_reservations WHERE issuer=$4 AND vc_id>=$1)
+ON CONFLICT (issuer, vc_id) DO NOTHING
+RETURNING $table_fields;
\ No newline at end of file
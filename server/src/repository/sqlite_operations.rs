@@ -0,0 +1,491 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::errors::IssuerError;
+
+use super::models::{ChallengePurpose, ContractEvent, ContractOperation, HolderChallenge, IndexerCursor, IssuanceJob, IssuerIdentity, JobStatus, OwnershipTransfer, VcIdReservation};
+use super::operations::{ContractEventsExt, ContractOperationsExt, HoldersChallengesExt, IssuanceJobsExt, IssuerIdentityExt, OwnershipTransfersExt, VcIdReservationsExt};
+
+pub type SqliteClient = deadpool_sqlite::Object;
+
+/// Runs `f` on the blocking thread of the pooled connection
+pub(crate) async fn interact<T, F>(client: &SqliteClient, f: F) -> Result<T, IssuerError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Connection) -> Result<T, IssuerError> + Send + 'static,
+{
+    client.interact(f).await
+        .map_err(|err| IssuerError::SqliteInteractError(err.to_string()))?
+}
+
+/// SQLite has no timestamp type, times are stored as unix milliseconds
+pub(crate) fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_millis() as i64).unwrap_or_default()
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+/// Builds a model from a row selected with the columns in the field order of the struct
+trait FromSqliteRow: Sized {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self>;
+}
+
+impl FromSqliteRow for IssuerIdentity {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self { did: row.get(0)?, fragment: row.get(1)?, name: row.get(2)? })
+    }
+}
+
+impl FromSqliteRow for HolderChallenge {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            did_holder: row.get(0)?,
+            challenge: row.get(1)?,
+            expiration: from_millis(row.get(2)?),
+            purpose: row.get(3)?,
+            audience: row.get(4)?,
+        })
+    }
+}
+
+impl FromSqliteRow for IssuanceJob {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            did_holder: row.get(1)?,
+            credential_id: row.get(2)?,
+            credential_jwt: row.get(3)?,
+            wallet_signature: row.get(4)?,
+            wallet_address: row.get(5)?,
+            challenge: row.get(6)?,
+            signature_scheme: row.get(7)?,
+            issuance_date: row.get(8)?,
+            expiration_date: row.get(9)?,
+            status: row.get(10)?,
+            tx_hash: row.get(11)?,
+            error: row.get(12)?,
+            created_at: from_millis(row.get(13)?),
+            updated_at: from_millis(row.get(14)?),
+            issuer: row.get(15)?,
+        })
+    }
+}
+
+impl FromSqliteRow for ContractOperation {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            credential_id: row.get(2)?,
+            job_id: row.get(3)?,
+            status: row.get(4)?,
+            tx_hash: row.get(5)?,
+            error: row.get(6)?,
+            attempts: row.get(7)?,
+            alerted: row.get(8)?,
+            created_at: from_millis(row.get(9)?),
+            updated_at: from_millis(row.get(10)?),
+            issuer: row.get(11)?,
+        })
+    }
+}
+
+impl FromSqliteRow for ContractEvent {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            contract: row.get(0)?,
+            block_number: row.get(1)?,
+            block_hash: row.get(2)?,
+            tx_hash: row.get(3)?,
+            log_index: row.get(4)?,
+            event: row.get(5)?,
+            vc_id: row.get(6)?,
+            account: row.get(7)?,
+            data: row.get(8)?,
+        })
+    }
+}
+
+impl FromSqliteRow for IndexerCursor {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self { contract: row.get(0)?, block_number: row.get(1)?, block_hash: row.get(2)? })
+    }
+}
+
+impl FromSqliteRow for VcIdReservation {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self { vc_id: row.get(0)?, job_id: row.get(1)?, reserved_at: from_millis(row.get(2)?), issuer: row.get(3)? })
+    }
+}
+
+impl FromSqliteRow for OwnershipTransfer {
+    fn from_sqlite_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            contract: row.get(1)?,
+            current_owner: row.get(2)?,
+            new_owner: row.get(3)?,
+            challenge: row.get(4)?,
+            status: row.get(5)?,
+            tx_hash: row.get(6)?,
+            error: row.get(7)?,
+            created_at: from_millis(row.get(8)?),
+            updated_at: from_millis(row.get(9)?),
+        })
+    }
+}
+
+fn query_opt<T: FromSqliteRow>(conn: &Connection, stmt: &str, params: impl rusqlite::Params) -> Result<Option<T>, IssuerError> {
+    Ok(conn.prepare_cached(stmt)?.query_row(params, T::from_sqlite_row).optional()?)
+}
+
+fn query_all<T: FromSqliteRow>(conn: &Connection, stmt: &str, params: impl rusqlite::Params) -> Result<Vec<T>, IssuerError> {
+    let mut stmt = conn.prepare_cached(stmt)?;
+    let rows = stmt.query_map(params, T::from_sqlite_row)?;
+    Ok(rows.collect::<rusqlite::Result<Vec<T>>>()?)
+}
+
+#[async_trait]
+impl IssuerIdentityExt for SqliteClient {
+    async fn get_identity_did(&self, name: &str) -> Result<IssuerIdentity, IssuerError> {
+        let name = name.to_owned();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/identities_get.sql"), [name])?
+                .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn insert_identity_issuer(&self, identity: &IssuerIdentity) -> Result<IssuerIdentity, IssuerError> {
+        let identity = identity.clone();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/identities_insert.sql"), params![identity.did, identity.fragment, identity.name])?
+                .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+}
+
+#[async_trait]
+impl HoldersChallengesExt for SqliteClient {
+    async fn consume_challenge(&self, did: &str, nonce: &str, purpose: ChallengePurpose, audience: &str) -> Result<HolderChallenge, IssuerError> {
+        let (did, nonce, audience) = (did.to_owned(), nonce.to_owned(), audience.to_owned());
+        interact(self, move |conn| {
+            let stmt = include_str!("./sql/sqlite/holders_challenges_consume.sql");
+            match query_opt(conn, stmt, params![did, nonce, purpose.as_str(), audience])? {
+                Some(challenge) => Ok(challenge),
+                None => {
+                    log::warn!("Rejected unknown or already used {} challenge of {}", purpose.as_str(), did);
+                    Err(IssuerError::NonExistingRequestError)
+                }
+            }
+        }).await
+    }
+
+    async fn insert_challenge(&self, holder_challenge: &HolderChallenge) -> Result<HolderChallenge, IssuerError> {
+        let challenge = holder_challenge.clone();
+        interact(self, move |conn| {
+            // an expired challenge does not count as pending
+            let transaction = conn.transaction()?;
+            transaction.execute(
+                include_str!("./sql/sqlite/holders_challenges_remove_expired.sql"),
+                params![challenge.did_holder, to_millis(SystemTime::now())],
+            )?;
+            let inserted = query_opt(&transaction, include_str!("./sql/sqlite/holders_challenges_insert.sql"), params![
+                challenge.did_holder,
+                to_millis(challenge.expiration),
+                challenge.challenge,
+                challenge.purpose,
+                challenge.audience,
+            ])?;
+            transaction.commit()?;
+            inserted.ok_or(IssuerError::ChallengePendingError) // the holder already has a pending challenge
+        }).await
+    }
+
+    async fn cleanup_challenges(&self) -> Result<u64, anyhow::Error> {
+        interact(self, |conn| {
+            let removed = conn.execute(include_str!("./sql/sqlite/holders_challenges_cleanup.sql"), [to_millis(SystemTime::now())])?;
+            Ok(removed as u64)
+        }).await
+        .map_err(|e| anyhow!("SQL Query delete failed: {}", e))
+    }
+}
+
+#[async_trait]
+impl IssuanceJobsExt for SqliteClient {
+    async fn insert_issuance_job(&self, job: &IssuanceJob) -> Result<IssuanceJob, IssuerError> {
+        let job = job.clone();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/issuance_jobs_insert.sql"), params![
+                job.id,
+                job.did_holder,
+                job.credential_id,
+                job.credential_jwt,
+                job.wallet_signature,
+                job.wallet_address,
+                job.challenge,
+                job.signature_scheme,
+                job.issuance_date,
+                job.expiration_date,
+                job.status,
+                to_millis(job.created_at),
+                job.issuer,
+            ])?
+            .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn get_issuance_job(&self, id: &str) -> Result<IssuanceJob, IssuerError> {
+        let id = id.to_owned();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/issuance_jobs_get.sql"), [id])?
+                .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn get_issuance_jobs_by_status(&self, status: JobStatus) -> Result<Vec<IssuanceJob>, IssuerError> {
+        interact(self, move |conn| {
+            query_all(conn, include_str!("./sql/sqlite/issuance_jobs_get_by_status.sql"), [status.as_str()])
+        }).await
+    }
+
+    async fn update_issuance_job(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        let id = id.to_owned();
+        interact(self, move |conn| {
+            conn.execute(
+                include_str!("./sql/sqlite/issuance_jobs_update.sql"),
+                params![id, status.as_str(), tx_hash, error, to_millis(SystemTime::now())],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn prune_issuance_jobs(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        interact(self, move |conn| {
+            // the audit trail in contract_operations outlives the jobs, references are detached
+            let transaction = conn.transaction()?;
+            transaction.execute(include_str!("./sql/sqlite/contract_operations_detach_jobs.sql"), [to_millis(before)])?;
+            let removed = transaction.execute(include_str!("./sql/sqlite/issuance_jobs_prune.sql"), [to_millis(before)])?;
+            transaction.commit()?;
+            Ok(removed as u64)
+        }).await
+    }
+}
+
+#[async_trait]
+impl ContractOperationsExt for SqliteClient {
+    async fn insert_operation(&self, operation: &ContractOperation) -> Result<ContractOperation, IssuerError> {
+        let operation = operation.clone();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/contract_operations_insert.sql"), params![
+                operation.id,
+                operation.kind,
+                operation.credential_id,
+                operation.job_id,
+                operation.status,
+                to_millis(operation.created_at),
+                operation.issuer,
+            ])?
+            .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn get_operations_by_status(&self, status: JobStatus) -> Result<Vec<ContractOperation>, IssuerError> {
+        interact(self, move |conn| {
+            query_all(conn, include_str!("./sql/sqlite/contract_operations_get_by_status.sql"), [status.as_str()])
+        }).await
+    }
+
+    async fn get_latest_operation_by_job(&self, job_id: &str) -> Result<ContractOperation, IssuerError> {
+        let job_id = job_id.to_owned();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/contract_operations_get_latest_by_job.sql"), params![job_id])?
+                .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn update_operation(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        let id = id.to_owned();
+        interact(self, move |conn| {
+            conn.execute(
+                include_str!("./sql/sqlite/contract_operations_update.sql"),
+                params![id, status.as_str(), tx_hash, error, to_millis(SystemTime::now())],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn resubmit_operation(&self, id: &str, tx_hash: &str) -> Result<(), IssuerError> {
+        let (id, tx_hash) = (id.to_owned(), tx_hash.to_owned());
+        interact(self, move |conn| {
+            conn.execute(
+                include_str!("./sql/sqlite/contract_operations_resubmit.sql"),
+                params![id, tx_hash, to_millis(SystemTime::now())],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn mark_operation_alerted(&self, id: &str) -> Result<(), IssuerError> {
+        let id = id.to_owned();
+        interact(self, move |conn| {
+            conn.execute(include_str!("./sql/sqlite/contract_operations_mark_alerted.sql"), [id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn prune_operations(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        interact(self, move |conn| {
+            let removed = conn.execute(include_str!("./sql/sqlite/contract_operations_prune.sql"), [to_millis(before)])?;
+            Ok(removed as u64)
+        }).await
+    }
+}
+
+#[async_trait]
+impl ContractEventsExt for SqliteClient {
+    async fn insert_event(&self, event: &ContractEvent) -> Result<(), IssuerError> {
+        let event = event.clone();
+        interact(self, move |conn| {
+            conn.execute(include_str!("./sql/sqlite/contract_events_insert.sql"), params![
+                event.contract,
+                event.block_number,
+                event.block_hash,
+                event.tx_hash,
+                event.log_index,
+                event.event,
+                event.vc_id,
+                event.account,
+                event.data,
+            ])?;
+            Ok(())
+        }).await
+    }
+
+    async fn remove_events_from(&self, contract: &str, block_number: i64) -> Result<(), IssuerError> {
+        let contract = contract.to_owned();
+        interact(self, move |conn| {
+            conn.execute(include_str!("./sql/sqlite/contract_events_remove_from.sql"), params![contract, block_number])?;
+            Ok(())
+        }).await
+    }
+
+    async fn get_events_by_vc_id(&self, contract: &str, vc_id: &str) -> Result<Vec<ContractEvent>, IssuerError> {
+        let (contract, vc_id) = (contract.to_owned(), vc_id.to_owned());
+        interact(self, move |conn| {
+            query_all(conn, include_str!("./sql/sqlite/contract_events_get_by_vc_id.sql"), params![contract, vc_id])
+        }).await
+    }
+
+    async fn get_event_block_before(&self, contract: &str, block_number: i64) -> Result<IndexerCursor, IssuerError> {
+        let contract = contract.to_owned();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/contract_events_get_block_before.sql"), params![contract, block_number])?
+                .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn get_indexer_cursor(&self, contract: &str) -> Result<IndexerCursor, IssuerError> {
+        let contract = contract.to_owned();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/indexer_cursors_get.sql"), [contract])?
+                .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn set_indexer_cursor(&self, cursor: &IndexerCursor) -> Result<(), IssuerError> {
+        let cursor = cursor.clone();
+        interact(self, move |conn| {
+            conn.execute(
+                include_str!("./sql/sqlite/indexer_cursors_upsert.sql"),
+                params![cursor.contract, cursor.block_number, cursor.block_hash],
+            )?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl VcIdReservationsExt for SqliteClient {
+    async fn reserve_vc_id(&self, issuer: &str, first_free_id: i64, job_id: &str) -> Result<VcIdReservation, IssuerError> {
+        let (issuer, job_id) = (issuer.to_owned(), job_id.to_owned());
+        // writers are serialized by SQLite, the first candidate is never taken concurrently
+        interact(self, move |conn| {
+            query_opt(
+                conn,
+                include_str!("./sql/sqlite/vc_id_reservations_reserve.sql"),
+                params![first_free_id, job_id, to_millis(SystemTime::now()), issuer],
+            )?
+            .ok_or(IssuerError::OtherError("VC id reservation failed".to_owned()))
+        }).await
+    }
+
+    async fn release_vc_id(&self, job_id: &str) -> Result<(), IssuerError> {
+        let job_id = job_id.to_owned();
+        interact(self, move |conn| {
+            conn.execute(include_str!("./sql/sqlite/vc_id_reservations_release.sql"), [job_id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn prune_vc_id_reservations(&self, issuer: &str, first_free_id: i64) -> Result<u64, IssuerError> {
+        let issuer = issuer.to_owned();
+        interact(self, move |conn| {
+            let removed = conn.execute(include_str!("./sql/sqlite/vc_id_reservations_prune.sql"), params![issuer, first_free_id])?;
+            Ok(removed as u64)
+        }).await
+    }
+}
+
+#[async_trait]
+impl OwnershipTransfersExt for SqliteClient {
+    async fn insert_ownership_transfer(&self, transfer: &OwnershipTransfer) -> Result<OwnershipTransfer, IssuerError> {
+        let transfer = transfer.clone();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/ownership_transfers_insert.sql"), params![
+                transfer.id,
+                transfer.contract,
+                transfer.current_owner,
+                transfer.new_owner,
+                transfer.challenge,
+                transfer.status,
+                to_millis(transfer.created_at),
+            ])?
+            .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn get_latest_ownership_transfer(&self, contract: &str, status: JobStatus) -> Result<OwnershipTransfer, IssuerError> {
+        let contract = contract.to_owned();
+        interact(self, move |conn| {
+            query_opt(conn, include_str!("./sql/sqlite/ownership_transfers_get_latest.sql"), params![contract, status.as_str()])?
+                .ok_or(IssuerError::RowNotFound)
+        }).await
+    }
+
+    async fn update_ownership_transfer(&self, id: &str, status: JobStatus, tx_hash: Option<String>, error: Option<String>) -> Result<(), IssuerError> {
+        let id = id.to_owned();
+        interact(self, move |conn| {
+            conn.execute(
+                include_str!("./sql/sqlite/ownership_transfers_update.sql"),
+                params![id, status.as_str(), tx_hash, error, to_millis(SystemTime::now())],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn prune_ownership_transfers(&self, before: SystemTime) -> Result<u64, IssuerError> {
+        interact(self, move |conn| {
+            let removed = conn.execute(include_str!("./sql/sqlite/ownership_transfers_prune.sql"), [to_millis(before)])?;
+            Ok(removed as u64)
+        }).await
+    }
+}
@@ -0,0 +1,69 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+use deadpool_sqlite::{Hook, HookError, Pool, Runtime};
+
+use crate::errors::IssuerError;
+use crate::utils::configs::DatabaseConfig;
+
+/// Applied to every new connection: WAL lets readers run next to the single writer,
+/// the busy timeout makes concurrent writers wait instead of failing
+const CONNECTION_PRAGMAS: &str = "PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;";
+
+/// Database file of a `sqlite://path`, `sqlite:path` or `sqlite::memory:` url
+pub fn sqlite_path(url: &str) -> Result<String, IssuerError> {
+    let path = url.strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .ok_or(IssuerError::DatabaseConfigError(format!("not a sqlite url: {}", url)))?;
+    if path.is_empty() {
+        return Err(IssuerError::DatabaseConfigError("missing sqlite database path".to_owned()));
+    }
+    Ok(path.to_owned())
+}
+
+pub async fn init(url: &str, configuration: &DatabaseConfig) -> Result<Pool> {
+    log::info!("init sqlite database");
+
+    let path = sqlite_path(url)?;
+    // every connection to :memory: opens a distinct database
+    let max_size = if path == ":memory:" { 1 } else { configuration.db_max_pool_size.into() };
+
+    let pool = deadpool_sqlite::Config::new(&path)
+        .builder(Runtime::Tokio1)?
+        .max_size(max_size)
+        .wait_timeout(Some(Duration::from_secs(configuration.db_pool_wait_timeout)))
+        .post_create(Hook::async_fn(|conn, _| Box::pin(async move {
+            conn.interact(|conn| conn.execute_batch(CONNECTION_PRAGMAS)).await
+                .map_err(|err| HookError::Message(err.to_string().into()))?
+                .map_err(HookError::Backend)
+        })))
+        .build()?;
+    log::info!("pool sqlite database {}, max size {}", path, max_size);
+
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqlite_path_accepts_every_url_form() {
+        assert_eq!(sqlite_path("sqlite://data/issuer.db").unwrap(), "data/issuer.db");
+        assert_eq!(sqlite_path("sqlite:///var/lib/issuer.db").unwrap(), "/var/lib/issuer.db");
+        assert_eq!(sqlite_path("sqlite:issuer.db").unwrap(), "issuer.db");
+        assert_eq!(sqlite_path("sqlite::memory:").unwrap(), ":memory:");
+    }
+
+    #[test]
+    fn sqlite_path_rejects_other_urls() {
+        assert!(matches!(sqlite_path("postgres://localhost/identity"), Err(IssuerError::DatabaseConfigError(_))));
+        assert!(matches!(sqlite_path("sqlite://"), Err(IssuerError::DatabaseConfigError(_))));
+        assert!(matches!(sqlite_path("sqlite:"), Err(IssuerError::DatabaseConfigError(_))));
+    }
+}
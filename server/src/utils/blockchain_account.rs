@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::str::FromStr;
+
+use alloy::primitives::Address;
+use identity_iota::iota::IotaDocument;
+use identity_iota::verification::jwk::{EcCurve, Jwk};
+use identity_iota::verification::jwu::decode_b64;
+use identity_iota::verification::{MethodData, VerificationMethod};
+
+use crate::errors::IssuerError;
+
+/// Fragment historically used by the holders for the wallet method
+const ETH_ADDRESS_FRAGMENT: &str = "ethAddress";
+
+/// Method types that can carry an Ethereum account
+const BLOCKCHAIN_METHOD_TYPES: [&str; 4] = [
+    "EcdsaSecp256k1RecoveryMethod2020",
+    "EcdsaSecp256k1VerificationKey2019",
+    "JsonWebKey2020",
+    "JsonWebKey",
+];
+
+/// CAIP-10 account id of an EVM chain, e.g. `eip155:1074:0xab16...`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caip10Account {
+    pub chain_id: u64,
+    pub address: Address,
+}
+
+impl FromStr for Caip10Account {
+    type Err = IssuerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("eip155"), Some(chain_id), Some(address)) => Ok(Self {
+                chain_id: chain_id.parse().map_err(|_| IssuerError::InvalidVerificationMethodType)?,
+                address: address.parse().map_err(|_| IssuerError::AddressRecoveryError)?,
+            }),
+            _ => Err(IssuerError::InvalidVerificationMethodType),
+        }
+    }
+}
+
+/// Account named in a request, either CAIP-10 on the configured chain or a plain address
+pub fn requested_account(value: &str, chain_id: u64) -> Result<Address, IssuerError> {
+    if !value.contains(':') {
+        return value.parse().map_err(|_| IssuerError::AddressRecoveryError);
+    }
+    let account = Caip10Account::from_str(value)?;
+    if account.chain_id != chain_id {
+        return Err(IssuerError::WrongChainAccount(value.to_owned()));
+    }
+    Ok(account.address)
+}
+
+/// Ethereum accounts of the holder on the configured chain.
+/// Methods carrying a `blockchainAccountId` (CAIP-10) or a secp256k1 `publicKeyJwk` are considered,
+/// the `#ethAddress` one comes first.
+pub fn holder_accounts(document: &IotaDocument, chain_id: u64) -> Result<Vec<Address>, IssuerError> {
+    let mut methods: Vec<&VerificationMethod> = document.methods(None)
+        .into_iter()
+        .filter(|method| BLOCKCHAIN_METHOD_TYPES.contains(&method.type_().as_str()))
+        .collect();
+    if methods.is_empty() {
+        return Err(IssuerError::EthMethodNotFound);
+    }
+    methods.sort_by_key(|method| method.id().fragment() != Some(ETH_ADDRESS_FRAGMENT));
+
+    let mut accounts = Vec::new();
+    let mut other_chains = Vec::new();
+    for method in methods {
+        match method_account(method, chain_id) {
+            Ok(Some(account)) if account.chain_id == chain_id => {
+                if !accounts.contains(&account.address) {
+                    accounts.push(account.address);
+                }
+            }
+            Ok(Some(account)) => other_chains.push(format!("eip155:{}:{}", account.chain_id, account.address)),
+            Ok(None) => {},
+            Err(err) => log::debug!("Skipping method {}: {}", method.id(), err),
+        }
+    }
+
+    match (accounts.is_empty(), other_chains.is_empty()) {
+        (false, _) => Ok(accounts),
+        (true, false) => Err(IssuerError::WrongChainAccount(other_chains.join(", "))),
+        (true, true) => Err(IssuerError::EthMethodNotFound),
+    }
+}
+
+/// Account carried by a single method, keys have no chain so they are bound to `chain_id`
+fn method_account(method: &VerificationMethod, chain_id: u64) -> Result<Option<Caip10Account>, IssuerError> {
+    match method.data() {
+        MethodData::Custom(data) if data.name == "blockchainAccountId" => {
+            let account = data.data.as_str().ok_or(IssuerError::InvalidVerificationMethodType)?;
+            Caip10Account::from_str(account).map(Some)
+        }
+        MethodData::PublicKeyJwk(jwk) => jwk_address(jwk).map(|address| Some(Caip10Account { chain_id, address })),
+        _ => Ok(None),
+    }
+}
+
+/// Ethereum address of a secp256k1 JWK, i.e. last 20 bytes of keccak(x || y)
+fn jwk_address(jwk: &Jwk) -> Result<Address, IssuerError> {
+    let params = jwk.try_ec_params().map_err(|_| IssuerError::InvalidVerificationMethodType)?;
+    if params.try_ec_curve().ok() != Some(EcCurve::Secp256K1) {
+        return Err(IssuerError::InvalidVerificationMethodType);
+    }
+    let mut public_key = decode_b64(&params.x).map_err(|_| IssuerError::InvalidVerificationMethodType)?;
+    public_key.extend(decode_b64(&params.y).map_err(|_| IssuerError::InvalidVerificationMethodType)?);
+    if public_key.len() != 64 {
+        return Err(IssuerError::InvalidVerificationMethodType);
+    }
+    Ok(Address::from_raw_public_key(&public_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::signers::local::PrivateKeySigner;
+    use identity_iota::core::FromJson;
+    use identity_iota::iota::NetworkName;
+    use identity_iota::verification::jwk::JwkParamsEc;
+    use identity_iota::verification::jwu::encode_b64;
+    use identity_iota::verification::MethodScope;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const CHAIN_ID: u64 = 1074;
+    const ADDRESS: &str = "0xAb16A96D359eC26a11e2C2b3d8f8B8942d5Bfcdb";
+
+    fn secp256k1_jwk(signer: &PrivateKeySigner) -> Jwk {
+        let point = signer.credential().verifying_key().to_encoded_point(false);
+        let mut params = JwkParamsEc::new();
+        params.crv = EcCurve::Secp256K1.name().to_owned();
+        params.x = encode_b64(point.x().unwrap());
+        params.y = encode_b64(point.y().unwrap());
+        Jwk::from_params(params)
+    }
+
+    fn document(methods: Vec<Value>) -> IotaDocument {
+        let mut document = IotaDocument::new(&NetworkName::try_from("iota").unwrap());
+        for mut method in methods {
+            method["id"] = json!(format!("{}#{}", document.id(), method["id"].as_str().unwrap()));
+            method["controller"] = json!(document.id().to_string());
+            let method = VerificationMethod::from_json_value(method).unwrap();
+            document.insert_method(method, MethodScope::VerificationMethod).unwrap();
+        }
+        document
+    }
+
+    fn caip10_method(fragment: &str, account: &str) -> Value {
+        json!({ "id": fragment, "type": "EcdsaSecp256k1RecoveryMethod2020", "blockchainAccountId": account })
+    }
+
+    #[test]
+    fn parses_caip10_accounts() {
+        let account = Caip10Account::from_str(&format!("eip155:{}:{}", CHAIN_ID, ADDRESS)).unwrap();
+        assert_eq!(account, Caip10Account { chain_id: CHAIN_ID, address: ADDRESS.parse().unwrap() });
+
+        assert!(Caip10Account::from_str(&format!("bip122:000000000019d6689c085ae165831e93:{}", ADDRESS)).is_err());
+        assert!(Caip10Account::from_str(&format!("eip155:mainnet:{}", ADDRESS)).is_err());
+        assert!(matches!(Caip10Account::from_str("eip155:1074:0x1234"), Err(IssuerError::AddressRecoveryError)));
+        assert!(Caip10Account::from_str(ADDRESS).is_err());
+    }
+
+    #[test]
+    fn requested_account_must_be_on_the_configured_chain() {
+        let address: Address = ADDRESS.parse().unwrap();
+        assert_eq!(requested_account(ADDRESS, CHAIN_ID).unwrap(), address);
+        assert_eq!(requested_account(&format!("eip155:{}:{}", CHAIN_ID, ADDRESS), CHAIN_ID).unwrap(), address);
+        assert!(matches!(requested_account(&format!("eip155:1:{}", ADDRESS), CHAIN_ID), Err(IssuerError::WrongChainAccount(_))));
+        assert!(requested_account("not an address", CHAIN_ID).is_err());
+    }
+
+    #[test]
+    fn jwk_address_matches_the_key_address() {
+        let signer = PrivateKeySigner::random();
+        assert_eq!(jwk_address(&secp256k1_jwk(&signer)).unwrap(), signer.address());
+
+        let mut other_curve = JwkParamsEc::new();
+        other_curve.crv = EcCurve::P256.name().to_owned();
+        other_curve.x = encode_b64([1u8; 32]);
+        other_curve.y = encode_b64([2u8; 32]);
+        assert!(jwk_address(&Jwk::from_params(other_curve)).is_err());
+    }
+
+    #[test]
+    fn holder_accounts_come_from_caip10_and_jwk_methods() {
+        let signer = PrivateKeySigner::random();
+        let mut jwk_method = json!({ "id": "key-1", "type": "JsonWebKey2020" });
+        jwk_method["publicKeyJwk"] = serde_json::to_value(secp256k1_jwk(&signer)).unwrap();
+        let document = document(vec![
+            jwk_method,
+            caip10_method(ETH_ADDRESS_FRAGMENT, &format!("eip155:{}:{}", CHAIN_ID, ADDRESS)),
+            caip10_method("other-chain", &format!("eip155:1:{}", signer.address())),
+        ]);
+
+        // the #ethAddress method comes first, accounts of other chains are left out
+        assert_eq!(holder_accounts(&document, CHAIN_ID).unwrap(), vec![ADDRESS.parse().unwrap(), signer.address()]);
+    }
+
+    #[test]
+    fn holder_accounts_report_the_accounts_of_other_chains() {
+        let document = document(vec![caip10_method("wallet", &format!("eip155:1:{}", ADDRESS))]);
+        assert!(matches!(holder_accounts(&document, CHAIN_ID), Err(IssuerError::WrongChainAccount(_))));
+        assert!(matches!(holder_accounts(&self::document(vec![]), CHAIN_ID), Err(IssuerError::EthMethodNotFound)));
+    }
+}
@@ -0,0 +1,188 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::Path;
+use std::time::Duration;
+
+use alloy::primitives::{Address, TxHash};
+use alloy::providers::{DynProvider, Provider};
+use serde::{Deserialize, Serialize};
+
+use crate::contracts::Identity::{self, OwnershipTransferred};
+use crate::errors::IssuerError;
+use crate::utils::eth::{send_call, wait_for_tx_event, TxState};
+use crate::utils::gas::GasStrategy;
+
+/// Contracts deployed by the issuer, read back at every start
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractsState {
+    pub chain_id: u64,
+    pub identity_sc_address: Address,
+    pub deployment_tx: TxHash,
+    pub deployment_block: u64,
+}
+
+impl ContractsState {
+    /// None when no contract has been deployed yet
+    pub fn load(path: &Path) -> Result<Option<Self>, IssuerError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| IssuerError::OtherError(format!("Cannot read {}: {}", path.display(), err)))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|err| IssuerError::OtherError(format!("Invalid contracts state {}: {}", path.display(), err)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), IssuerError> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|err| IssuerError::OtherError(err.to_string()))?;
+        std::fs::write(path, content)
+            .map_err(|err| IssuerError::OtherError(format!("Cannot write {}: {}", path.display(), err)))
+    }
+}
+
+/// Deploys the Identity smart contract compiled into the binary, the signer of the provider becomes its owner.
+/// Waits for the configured confirmations, the constructor emits OwnershipTransferred.
+pub async fn deploy_identity(
+    provider: &DynProvider,
+    chain_id: u64,
+    gas_strategy: &GasStrategy,
+    confirmations: u64,
+    timeout: Duration,
+) -> Result<ContractsState, IssuerError> {
+    if Identity::BYTECODE.is_empty() {
+        return Err(IssuerError::ContractError("Identity.json has no creation bytecode (abstract contract or ABI only)".to_owned()));
+    }
+
+    let deploy = gas_strategy.apply(Identity::deploy_builder(provider.clone())).await?;
+    let tx_hash = *send_call(deploy).await
+        .map_err(|err| IssuerError::ContractError(format!("Identity deployment failed: {}", err)))?
+        .tx_hash();
+    log::info!("Identity deployment transaction sent: {}", tx_hash);
+
+    match wait_for_tx_event::<OwnershipTransferred>(provider, tx_hash, confirmations, timeout).await? {
+        TxState::Confirmed => {},
+        TxState::Failed(reason) => return Err(IssuerError::ContractError(reason)),
+        TxState::Pending | TxState::Dropped => return Err(IssuerError::ContractError(
+            format!("Identity deployment {} not confirmed within {}s", tx_hash, timeout.as_secs())
+        )),
+    }
+
+    let receipt = provider.get_transaction_receipt(tx_hash).await
+        .map_err(|err| IssuerError::ContractError(format!("Receipt request failed: {}", err)))?
+        .ok_or(IssuerError::ContractError(format!("Receipt of {} not found", tx_hash)))?;
+    let identity_sc_address = receipt.contract_address
+        .ok_or(IssuerError::ContractError(format!("No contract created by {}", tx_hash)))?;
+
+    Ok(ContractsState {
+        chain_id,
+        identity_sc_address,
+        deployment_tx: tx_hash,
+        deployment_block: receipt.block_number.unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::{Child, Command, Stdio};
+
+    use alloy::providers::ProviderBuilder;
+    use alloy::signers::local::PrivateKeySigner;
+
+    use crate::utils::configs::GasConfig;
+
+    use super::*;
+
+    /// First dev account of anvil
+    const ANVIL_KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const ANVIL_CHAIN: u64 = 31337;
+
+    /// Kills the node when the test ends
+    struct Anvil(Child);
+
+    impl Drop for Anvil {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    async fn anvil(port: u16) -> (Anvil, DynProvider) {
+        let child = Command::new("anvil")
+            .args(["--port", &port.to_string(), "--chain-id", &ANVIL_CHAIN.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("cannot spawn anvil, install foundry to run this test");
+        let node = Anvil(child);
+        let signer = ANVIL_KEY.parse::<PrivateKeySigner>().unwrap();
+        let provider = ProviderBuilder::new()
+            .wallet(signer)
+            .connect_http(format!("http://127.0.0.1:{}", port).parse().unwrap());
+        provider.client().set_poll_interval(Duration::from_millis(100));
+        let provider = DynProvider::new(provider);
+        for _ in 0..50 {
+            if provider.get_chain_id().await.is_ok() {
+                return (node, provider);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("anvil did not start on port {}", port);
+    }
+
+    fn gas_strategy() -> GasStrategy {
+        GasStrategy::new(&GasConfig {
+            gas_strategy: None,
+            gas_price: None,
+            gas_fee_multiplier: None,
+            gas_limit_multiplier: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+        }, ANVIL_CHAIN)
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let path = std::env::temp_dir().join(format!("contracts-state-{}.json", std::process::id()));
+        assert!(ContractsState::load(&path).unwrap().is_none());
+
+        let state = ContractsState {
+            chain_id: ANVIL_CHAIN,
+            identity_sc_address: Address::repeat_byte(1),
+            deployment_tx: TxHash::repeat_byte(2),
+            deployment_block: 7,
+        };
+        state.save(&path).unwrap();
+        let loaded = ContractsState::load(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.chain_id, state.chain_id);
+        assert_eq!(loaded.identity_sc_address, state.identity_sc_address);
+        assert_eq!(loaded.deployment_tx, state.deployment_tx);
+        assert_eq!(loaded.deployment_block, state.deployment_block);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires anvil"]
+    async fn deploys_identity_on_anvil() {
+        let (_node, provider) = anvil(18545).await;
+
+        let result = deploy_identity(&provider, ANVIL_CHAIN, &gas_strategy(), 1, Duration::from_secs(30)).await;
+        if Identity::BYTECODE.is_empty() {
+            assert!(matches!(result, Err(IssuerError::ContractError(_))));
+            return;
+        }
+        let state = result.unwrap();
+        assert_eq!(state.chain_id, ANVIL_CHAIN);
+        assert!(state.deployment_block > 0);
+
+        // the signer of the provider owns the contract
+        let identity = Identity::new(state.identity_sc_address, provider.clone());
+        let signer = ANVIL_KEY.parse::<PrivateKeySigner>().unwrap();
+        assert_eq!(identity.owner().call().await.unwrap(), signer.address());
+        assert!(!provider.get_code_at(state.identity_sc_address).await.unwrap().is_empty());
+    }
+}
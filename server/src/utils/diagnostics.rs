@@ -0,0 +1,176 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fmt;
+
+use alloy::primitives::{Address, U256};
+use alloy::providers::{DynProvider, Provider};
+
+use crate::contracts::Identity::{self, IdentityCalls, IdentityInstance};
+use crate::utils::ownership::get_owner;
+use crate::utils::wallet_binding::{verifies_erc1271, WalletBindingVerifier, WalletSignatureScheme};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self { name, status, detail: detail.into() }
+    }
+}
+
+/// Outcome of the startup self-check of the DLT configuration
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticsReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl DiagnosticsReport {
+    /// True when no check failed, warnings are allowed
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(|check| check.status != CheckStatus::Failed)
+    }
+
+    /// Writes every check to the log, with a level matching its status
+    pub fn log(&self) {
+        for check in &self.checks {
+            match check.status {
+                CheckStatus::Ok => log::info!("[self-check] {}: {}", check.name, check.detail),
+                CheckStatus::Warning => log::warn!("[self-check] {}: {}", check.name, check.detail),
+                CheckStatus::Failed => log::error!("[self-check] {}: {}", check.name, check.detail),
+            }
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = match check.status {
+                CheckStatus::Ok => "OK",
+                CheckStatus::Warning => "WARN",
+                CheckStatus::Failed => "FAIL",
+            };
+            writeln!(f, "[{:<4}] {:<20} {}", status, check.name, check.detail)?;
+        }
+        write!(f, "Result: {}", if self.is_healthy() { "healthy" } else { "unhealthy" })
+    }
+}
+
+/// Smart contracts and signer expected by the issuer
+pub struct DiagnosticsTarget<'a> {
+    pub provider: &'a DynProvider,
+    /// Identity contract of every issuer, by issuer name
+    pub identity_contracts: &'a [(String, IdentityInstance<DynProvider>)],
+    pub factory: Address,
+    pub fresc: Address,
+    pub chain_id: u64,
+    pub signer: Address,
+    pub min_signer_balance: U256,
+    /// Scheme of the holder wallet signatures verified by the Identity contracts
+    pub signature_scheme: WalletSignatureScheme,
+}
+
+/// Verifies RPC reachability, chain id, deployed contracts, ownership, wallet signature scheme and signer funds
+pub async fn run_diagnostics(target: DiagnosticsTarget<'_>) -> DiagnosticsReport {
+    let provider = target.provider;
+    let mut report = DiagnosticsReport::default();
+
+    match provider.get_block_number().await {
+        Ok(block) => report.checks.push(CheckResult::new("rpc", CheckStatus::Ok, format!("reachable, latest block {}", block))),
+        Err(err) => {
+            report.checks.push(CheckResult::new("rpc", CheckStatus::Failed, format!("unreachable: {}", err)));
+            // every other check needs the RPC
+            return report;
+        }
+    }
+
+    report.checks.push(match provider.get_chain_id().await {
+        Ok(chain_id) if chain_id == target.chain_id => CheckResult::new("chain id", CheckStatus::Ok, chain_id.to_string()),
+        Ok(chain_id) => CheckResult::new("chain id", CheckStatus::Failed, format!("node reports {}, configured {}", chain_id, target.chain_id)),
+        Err(err) => CheckResult::new("chain id", CheckStatus::Failed, err.to_string()),
+    });
+
+    report.checks.push(check_code("factory contract", provider, target.factory).await);
+    report.checks.push(check_code("fresc contract", provider, target.fresc).await);
+
+    // one identity contract per issuer, all of them written by the same signer
+    for (issuer, identity_sc) in target.identity_contracts {
+        let mut check = check_identity_code(provider, *identity_sc.address()).await;
+        check.detail = format!("[{}] {}", issuer, check.detail);
+        report.checks.push(check);
+
+        report.checks.push(match get_owner(identity_sc).await {
+            Ok(owner) if owner == target.signer => CheckResult::new("contract owner", CheckStatus::Ok, format!("[{}] signer {} owns the contract", issuer, owner)),
+            Ok(owner) => CheckResult::new("contract owner", CheckStatus::Failed, format!("[{}] owner is {}, signer is {}", issuer, owner, target.signer)),
+            Err(err) => CheckResult::new("contract owner", CheckStatus::Failed, format!("[{}] {}", issuer, err)),
+        });
+
+        // a scheme the contract does not verify makes every addUser revert
+        let contract_wallets = verifies_erc1271(provider, *identity_sc.address()).await.unwrap_or_default();
+        let verifier = WalletBindingVerifier::new(target.signature_scheme, target.chain_id, *identity_sc.address(), contract_wallets);
+        report.checks.push(match verifier.check_contract(provider).await {
+            Ok(()) => CheckResult::new("wallet signatures", CheckStatus::Ok, format!(
+                "[{}] {}, smart contract wallets {}",
+                issuer, target.signature_scheme.as_str(), if contract_wallets { "accepted (ERC-1271)" } else { "rejected" }
+            )),
+            Err(reason) => CheckResult::new("wallet signatures", CheckStatus::Failed, format!("[{}] {}", issuer, reason)),
+        });
+    }
+
+    report.checks.push(match provider.get_balance(target.signer).await {
+        Ok(balance) if balance.is_zero() => CheckResult::new("signer balance", CheckStatus::Failed, format!("{} has no funds", target.signer)),
+        Ok(balance) if balance < target.min_signer_balance => CheckResult::new("signer balance", CheckStatus::Warning, format!("{} wei, below {} wei", balance, target.min_signer_balance)),
+        Ok(balance) => CheckResult::new("signer balance", CheckStatus::Ok, format!("{} wei", balance)),
+        Err(err) => CheckResult::new("signer balance", CheckStatus::Failed, err.to_string()),
+    });
+
+    report
+}
+
+async fn check_code(name: &'static str, provider: &DynProvider, address: Address) -> CheckResult {
+    match provider.get_code_at(address).await {
+        Ok(code) if code.is_empty() => CheckResult::new(name, CheckStatus::Failed, format!("no code at {}", address)),
+        Ok(code) => CheckResult::new(name, CheckStatus::Ok, format!("{} bytes at {}", code.len(), address)),
+        Err(err) => CheckResult::new(name, CheckStatus::Failed, err.to_string()),
+    }
+}
+
+/// Compares the deployed Identity contract with the compiled artifact.
+/// When the bytecode differs (e.g. other compiler settings) every ABI function selector must be found in it.
+async fn check_identity_code(provider: &DynProvider, address: Address) -> CheckResult {
+    const NAME: &str = "identity contract";
+    let code = match provider.get_code_at(address).await {
+        Ok(code) if code.is_empty() => return CheckResult::new(NAME, CheckStatus::Failed, format!("no code at {}", address)),
+        Ok(code) => code,
+        Err(err) => return CheckResult::new(NAME, CheckStatus::Failed, err.to_string()),
+    };
+
+    if code == Identity::DEPLOYED_BYTECODE {
+        return CheckResult::new(NAME, CheckStatus::Ok, format!("{} matches the compiled artifact", address));
+    }
+
+    let missing: Vec<String> = IdentityCalls::SELECTORS.iter()
+        .zip(IdentityCalls::VARIANT_NAMES)
+        .filter(|(selector, _)| !code.windows(4).any(|window| window == selector.as_slice()))
+        .map(|(_, name)| name.to_string())
+        .collect();
+
+    if missing.is_empty() {
+        CheckResult::new(NAME, CheckStatus::Warning, format!("{} differs from the compiled artifact but exposes the whole ABI", address))
+    } else {
+        CheckResult::new(NAME, CheckStatus::Failed, format!("{} does not expose {}", address, missing.join(", ")))
+    }
+}
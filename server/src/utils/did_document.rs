@@ -0,0 +1,167 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use clap::ValueEnum;
+use identity_iota::core::{Object, Url};
+use identity_iota::credential::LinkedDomainService;
+use identity_iota::did::DID;
+use identity_iota::document::Service;
+use identity_iota::iota::{IotaDocument, IotaIdentityClientExt};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::IssuerError;
+
+use super::iota::IotaState;
+use super::issuers::Issuer;
+
+/// Services the issuer can publish in its DID document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServiceKind {
+    /// Url of the credential issuer metadata
+    IssuerMetadata,
+    /// Url of the credential status list
+    StatusList,
+    /// Origin of a domain linked to the DID
+    LinkedDomain,
+}
+
+impl ServiceKind {
+    /// Value of the service `type`
+    pub fn service_type(&self) -> &'static str {
+        match self {
+            ServiceKind::IssuerMetadata => "CredentialIssuerMetadata",
+            ServiceKind::StatusList => "StatusList",
+            ServiceKind::LinkedDomain => "LinkedDomains",
+        }
+    }
+
+    /// Fragment of the service id when none is given
+    pub fn default_fragment(&self) -> &'static str {
+        match self {
+            ServiceKind::IssuerMetadata => "issuer-metadata",
+            ServiceKind::StatusList => "status-list",
+            ServiceKind::LinkedDomain => "linked-domain",
+        }
+    }
+}
+
+/// Service to add, an existing service with the same fragment is replaced
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceEntry {
+    pub kind: ServiceKind,
+    pub endpoint: Url,
+    pub fragment: Option<String>,
+}
+
+/// Changes to the issuer DID document, the verification methods are never touched
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DocumentUpdate {
+    pub add_services: Vec<ServiceEntry>,
+    /// Fragments of the services to remove
+    pub remove_services: Vec<String>,
+    /// Replaces `alsoKnownAs` when set, an empty list clears it
+    pub also_known_as: Option<Vec<Url>>,
+}
+
+impl DocumentUpdate {
+    /// Returns a copy of the document with the changes applied
+    pub fn apply(&self, document: &IotaDocument) -> Result<IotaDocument, IssuerError> {
+        let mut updated = document.clone();
+
+        for fragment in &self.remove_services {
+            let service_id = document.id().to_url().join(format!("#{}", fragment))?;
+            if updated.remove_service(&service_id).is_none() {
+                return Err(IssuerError::DidDocumentError(format!("no service #{} in the document", fragment)));
+            }
+        }
+
+        for entry in &self.add_services {
+            let fragment = entry.fragment.as_deref().unwrap_or(entry.kind.default_fragment());
+            let service_id = document.id().to_url().join(format!("#{}", fragment))?;
+            updated.remove_service(&service_id);
+            let service = Service::builder(Object::new())
+                .id(service_id)
+                .type_(entry.kind.service_type())
+                .service_endpoint(entry.endpoint.clone())
+                .build()
+                .map_err(|err| IssuerError::DidDocumentError(err.to_string()))?;
+            // verifiers only accept https origins without path
+            if entry.kind == ServiceKind::LinkedDomain {
+                LinkedDomainService::check_structure(&service)
+                    .map_err(|err| IssuerError::DidDocumentError(err.to_string()))?;
+            }
+            updated.insert_service(service)?;
+        }
+
+        if let Some(also_known_as) = &self.also_known_as {
+            let urls = updated.also_known_as_mut();
+            urls.clear();
+            for url in also_known_as {
+                urls.append(url.clone());
+            }
+        }
+        Ok(updated)
+    }
+}
+
+/// Services and `alsoKnownAs` entries removed (`-`) and added (`+`) by `updated`
+pub fn document_diff(current: &IotaDocument, updated: &IotaDocument) -> Vec<String> {
+    let describe_services = |document: &IotaDocument| -> Vec<String> {
+        document.service().iter().map(describe_service).collect()
+    };
+    let describe_aliases = |document: &IotaDocument| -> Vec<String> {
+        document.also_known_as().iter().map(|url| format!("alsoKnownAs {}", url)).collect()
+    };
+
+    let mut diff = Vec::new();
+    for (before, after) in [
+        (describe_services(current), describe_services(updated)),
+        (describe_aliases(current), describe_aliases(updated)),
+    ] {
+        diff.extend(before.iter().filter(|line| !after.contains(line)).map(|line| format!("- {}", line)));
+        diff.extend(after.iter().filter(|line| !before.contains(line)).map(|line| format!("+ {}", line)));
+    }
+    diff
+}
+
+fn describe_service(service: &Service) -> String {
+    let types: Vec<&str> = service.type_().iter().map(String::as_str).collect();
+    let endpoint = serde_json::to_string(service.service_endpoint()).unwrap_or_default();
+    format!("service #{} {} {}", service.id().fragment().unwrap_or_default(), types.join(","), endpoint)
+}
+
+/// Outcome of an update of the issuer DID document
+pub struct DocumentUpdateOutcome {
+    pub diff: Vec<String>,
+    /// Published document, or the document that would be published by a preview
+    pub document: IotaDocument,
+    pub published: bool,
+}
+
+/// Applies the update to the document currently on the ledger, so changes published
+/// by another replica are kept, then publishes it unless only a preview is requested
+pub async fn update_issuer_document(
+    iota_state: &IotaState,
+    issuer: &Issuer,
+    update: &DocumentUpdate,
+    publish: bool,
+) -> Result<DocumentUpdateOutcome, IssuerError> {
+    let current = iota_state.client.resolve_did(issuer.document().id()).await?;
+    let updated = update.apply(&current)?;
+    let diff = document_diff(&current, &updated);
+    if diff.is_empty() {
+        return Err(IssuerError::DidDocumentError("the update does not change the document".to_owned()));
+    }
+    if !publish {
+        return Ok(DocumentUpdateOutcome { diff, document: updated, published: false });
+    }
+
+    let document = iota_state.publish_document(updated).await?;
+    log::info!("DID document of issuer {} updated: {}", issuer.name, diff.join("; "));
+    issuer.set_document(document.clone());
+    Ok(DocumentUpdateOutcome { diff, document, published: true })
+}
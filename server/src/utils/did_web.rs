@@ -0,0 +1,53 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use identity_iota::core::{Object, Url};
+use identity_iota::did::{CoreDID, DID};
+use identity_iota::document::CoreDocument;
+use identity_iota::iota::IotaDocument;
+
+use crate::errors::IssuerError;
+
+use super::configs::IssuerUrl;
+
+/// `did:web` identity of the issuer host, mirroring the keys of the default issuer
+/// for the verifiers that cannot resolve IOTA DIDs
+pub struct DidWeb {
+    did: CoreDID,
+}
+
+impl DidWeb {
+    /// `did:web:<host>`, the port is percent-encoded as the method requires
+    pub fn new(issuer_url: &IssuerUrl) -> Result<Self, IssuerError> {
+        let host = issuer_url.domain()
+            .ok_or(IssuerError::InvalidDid(format!("{} has no domain for did:web", issuer_url)))?;
+        let id = match issuer_url.port() {
+            Some(port) => format!("did:web:{}%3A{}", host, port),
+            None => format!("did:web:{}", host),
+        };
+        Ok(Self { did: CoreDID::parse(id)? })
+    }
+
+    pub fn did(&self) -> &CoreDID {
+        &self.did
+    }
+
+    /// Document served at `/.well-known/did.json`: the verification methods of the IOTA document
+    /// under the did:web id, with the IOTA DID in `alsoKnownAs`
+    pub fn mirror(&self, document: &IotaDocument) -> Result<CoreDocument, IssuerError> {
+        let iota_did = Url::parse(document.id().as_str())
+            .map_err(|err| IssuerError::InvalidDid(err.to_string()))?;
+        let mut builder = CoreDocument::builder(Object::new())
+            .id(self.did.clone())
+            .also_known_as(iota_did);
+        for method in document.methods(None) {
+            let method = method.clone().map(|_| self.did.clone());
+            // did:web verifiers look for the credential keys among the assertion methods
+            builder = builder
+                .assertion_method(method.id().clone())
+                .verification_method(method);
+        }
+        builder.build().map_err(|err| IssuerError::InvalidDid(err.to_string()))
+    }
+}
@@ -0,0 +1,99 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::sync::Arc;
+
+use identity_iota::core::{Duration, Timestamp, Url};
+use identity_iota::credential::{DomainLinkageConfiguration, DomainLinkageCredentialBuilder, LinkedDomainService};
+use identity_iota::iota::IotaDocument;
+use identity_iota::storage::{JwkDocumentExt, JwsSignatureOptions};
+use tokio::sync::Mutex;
+
+use crate::errors::IssuerError;
+
+use super::configs::IssuerUrl;
+use super::iota::IotaState;
+use super::issuers::{Issuer, IssuerRegistry};
+
+/// Configuration signed for the issuers linked at that time
+struct SignedConfiguration {
+    /// Issuers covered by the configuration
+    dids: Vec<String>,
+    configuration: DomainLinkageConfiguration,
+    refresh_at: Timestamp,
+}
+
+/// Links the issuer DIDs to the origin of `ISSUER_URL` (DIF Well Known DID Configuration).
+/// A DID is listed once its document has a `LinkedDomains` service for the origin.
+pub struct DomainLinkage {
+    origin: Url,
+    validity_days: u32,
+    signed: Mutex<Option<SignedConfiguration>>,
+}
+
+impl DomainLinkage {
+    /// None when the issuer url has no https domain or the validity is 0
+    pub fn new(issuer_url: &IssuerUrl, validity_days: u32) -> Option<Self> {
+        if validity_days == 0 || issuer_url.scheme() != "https" || issuer_url.domain().is_none() {
+            return None;
+        }
+        let mut origin = issuer_url.clone();
+        origin.set_path("/");
+        origin.set_query(None);
+        origin.set_fragment(None);
+        Some(Self { origin, validity_days, signed: Mutex::new(None) })
+    }
+
+    pub fn origin(&self) -> &Url {
+        &self.origin
+    }
+
+    /// True when the document has a `LinkedDomains` service for the origin
+    pub fn is_linked(&self, document: &IotaDocument) -> bool {
+        document.service().iter()
+            .filter_map(|service| LinkedDomainService::try_from(service.clone()).ok())
+            .any(|service| service.domains().contains(&self.origin))
+    }
+
+    /// Configuration resource with a Domain Linkage credential for every linked issuer.
+    /// The credentials are signed again once half of their validity is elapsed or when the linked issuers change.
+    pub async fn configuration(&self, iota_state: &IotaState, issuers: &IssuerRegistry) -> Result<DomainLinkageConfiguration, IssuerError> {
+        let mut linked: Vec<Arc<Issuer>> = issuers.iter()
+            .filter(|issuer| self.is_linked(&issuer.document()))
+            .cloned()
+            .collect();
+        linked.sort_by(|a, b| a.name.cmp(&b.name));
+        let dids: Vec<String> = linked.iter().map(|issuer| issuer.identity.did.clone()).collect();
+
+        let mut signed = self.signed.lock().await;
+        if let Some(current) = signed.as_ref().filter(|current| current.dids == dids && Timestamp::now_utc() < current.refresh_at) {
+            return Ok(current.configuration.clone());
+        }
+
+        let now = Timestamp::now_utc();
+        let expiration = now.checked_add(Duration::days(self.validity_days))
+            .ok_or(IssuerError::OtherError("Domain linkage expiration out of range".to_owned()))?;
+        let mut linked_dids = Vec::new();
+        for issuer in &linked {
+            let document = issuer.document();
+            let credential = DomainLinkageCredentialBuilder::new()
+                .issuer(document.id().clone().into())
+                .origin(self.origin.clone())
+                .issuance_date(now)
+                .expiration_date(expiration)
+                .build()
+                .map_err(|err| IssuerError::OtherError(format!("Domain linkage credential error: {}", err)))?;
+            let jwt = document
+                .create_credential_jwt(&credential, &iota_state.key_storage, &issuer.identity.fragment, &JwsSignatureOptions::default(), None)
+                .await
+                .map_err(|err| IssuerError::OtherError(format!("Domain linkage signature error: {}", err)))?;
+            linked_dids.push(jwt);
+        }
+
+        let configuration = DomainLinkageConfiguration::new(linked_dids);
+        let refresh_at = now.checked_add(Duration::hours(self.validity_days.saturating_mul(12))).unwrap_or(expiration);
+        *signed = Some(SignedConfiguration { dids, configuration: configuration.clone(), refresh_at });
+        Ok(configuration)
+    }
+}
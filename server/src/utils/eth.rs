@@ -1,59 +1,181 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use alloy::contract::{CallBuilder, CallDecoder};
 use alloy::hex::FromHex;
 
 use alloy::primitives::Bytes;
+use alloy::primitives::TxHash;
 use alloy::primitives::U256;
-use alloy::providers::DynProvider;
+use alloy::providers::{DynProvider, PendingTransactionBuilder, Provider};
+use alloy::rpc::types::{BlockNumberOrTag, Filter};
 use alloy::sol_types::SolEvent;
-use identity_iota::credential::DecodedJwtCredential;
-use crate::contracts::Identity::IdentityInstance;
-use crate::contracts::Identity::VC_added;
+use crate::contracts::Identity::{IdentityInstance, VC_added};
 use crate::errors::IssuerError;
+use crate::utils::gas::GasStrategy;
 
+/// Blocks requested at a time when searching the contract logs
+const LOG_SEARCH_BATCH: u64 = 1000;
+/// Margin between the clock of the issuer and the timestamps of the blocks
+const CLOCK_DRIFT: u64 = 300;
 
+/// Held from the nonce assignment until the node accepts the transaction, so the issuance worker,
+/// the reconciler and the handlers never send two transactions with the same nonce
+static SEND_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Outcome of a transaction sent to a smart contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxState {
+    /// No receipt available yet, or not enough confirmations
+    Pending,
+    /// Unknown to the node, e.g. evicted from the mempool or reorged out
+    Dropped,
+    /// Mined with enough confirmations and the expected event was emitted
+    Confirmed,
+    /// Reverted or mined without the expected event
+    Failed(String),
+}
+
+/// Credential registered in the Identity contract by addUser
+pub struct UserRegistration {
+    pub credential_id: U256,
+    pub expiration_date: U256,
+    pub issuance_date: U256,
+    pub challenge: Bytes,
+    /// Hex signature of the holder wallet, 0x prefixed
+    pub wallet_signature: String,
+}
+
+/// Sends the addUser transaction and returns its hash without waiting for the receipt
+pub async fn submit_add_user(
+    identity_sc: &IdentityInstance<DynProvider>,
+    registration: &UserRegistration,
+    gas_strategy: &GasStrategy
+) -> Result<TxHash, IssuerError> {
+
+    let wallet_sign_hex = registration.wallet_signature.strip_prefix("0x")
+        .ok_or(IssuerError::OtherError("Error during strip prefix".to_owned()))?;
+    let wallet_sign_bytes = Bytes::from(Vec::from_hex(wallet_sign_hex).map_err(|_| IssuerError::OtherError("Conversion error".to_owned()))?);
 
-pub async fn update_identity_sc(
-    identity_sc: actix_web::web::Data<IdentityInstance<DynProvider>>,
-    decoded_jwt_credential: DecodedJwtCredential, 
-    credential_id: U256,
-    challenge: String, 
-    wallet_sign: &String, 
-    nonce: u64
-) -> Result<(), IssuerError> {
-
-    let wallet_sign_bytes = Bytes::from(Vec::from_hex(wallet_sign.strip_prefix("0x").ok_or(IssuerError::OtherError("Error during strip prefix".to_owned()))?.to_string()).map_err(|_| IssuerError::OtherError("Conversion error".to_owned()))?);
-    let challenge_bytes = Bytes::from(challenge.into_bytes());
-    let expiration_date = U256::from(decoded_jwt_credential.credential.expiration_date.ok_or(IssuerError::OtherError("Expiration date not found".to_owned()))?.to_unix());
-    let issuance_date = U256::from(decoded_jwt_credential.credential.issuance_date.to_unix());
-    
     let call = identity_sc.addUser(
-        credential_id, 
-        expiration_date,
-        issuance_date,
-        wallet_sign_bytes.into(), 
-        challenge_bytes.into()
-    )
-    .gas_price(10_000_000_000)
-    .nonce(nonce);
-
-    let receipt = call
-        .send()
+        registration.credential_id,
+        registration.expiration_date,
+        registration.issuance_date,
+        wallet_sign_bytes,
+        registration.challenge.clone()
+    );
+    let call = gas_strategy.apply(call).await?;
+
+    let pending_tx = send_call(call)
         .await
-        .map_err(|err| IssuerError::ContractError(format!("User registration failed: {}",err.to_string())))?
-        .with_timeout(Some(Duration::from_secs(20)))
-        .get_receipt()
+        .map_err(|err| IssuerError::ContractError(format!("User registration failed: {}", err)))?;
+
+    log::info!("addUser transaction sent: {}", pending_tx.tx_hash());
+    Ok(*pending_tx.tx_hash())
+}
+
+/// Sends the revokeVC transaction and returns its hash without waiting for the receipt
+pub async fn submit_revoke_vc(
+    identity_sc: &IdentityInstance<DynProvider>,
+    credential_id: U256,
+    gas_strategy: &GasStrategy
+) -> Result<TxHash, IssuerError> {
+    let call = gas_strategy.apply(identity_sc.revokeVC(credential_id)).await?;
+
+    let pending_tx = send_call(call)
         .await
-        .map_err(|err| IssuerError::ContractError(format!("User registration failed: {}",err.to_string())))?;
+        .map_err(|err| IssuerError::ContractError(err.to_string()))?;
 
-    // reading the log   
+    log::info!("revokeVC transaction sent: {}", pending_tx.tx_hash());
+    Ok(*pending_tx.tx_hash())
+}
+
+/// Sends a contract transaction of the issuer signer, every contract write goes through here.
+/// The nonce is assigned by the nonce filler of the provider from the pending transaction count.
+pub async fn send_call<P: Provider, D: CallDecoder>(call: CallBuilder<P, D>) -> alloy::contract::Result<PendingTransactionBuilder<alloy::network::Ethereum>> {
+    let _guard = SEND_LOCK.lock().await;
+    call.send().await
+}
+
+/// Looks for the event `E` in the receipt of the given transaction.
+/// The outcome is reported only once the receipt is `confirmations` blocks deep.
+pub async fn check_tx_event<E: SolEvent>(provider: &DynProvider, tx_hash: TxHash, confirmations: u64) -> Result<TxState, IssuerError> {
+    let receipt = match provider.get_transaction_receipt(tx_hash).await
+        .map_err(|err| IssuerError::ContractError(format!("Receipt request failed: {}", err)))? {
+        Some(receipt) => receipt,
+        None => {
+            let transaction = provider.get_transaction_by_hash(tx_hash).await
+                .map_err(|err| IssuerError::ContractError(format!("Transaction request failed: {}", err)))?;
+            return Ok(if transaction.is_some() { TxState::Pending } else { TxState::Dropped });
+        }
+    };
+
+    let block_number = match receipt.block_number {
+        Some(block_number) => block_number,
+        None => return Ok(TxState::Pending),
+    };
+    let head = provider.get_block_number().await
+        .map_err(|err| IssuerError::ContractError(format!("Block number request failed: {}", err)))?;
+    let depth = head.saturating_sub(block_number) + 1;
+    if depth < confirmations {
+        log::debug!("Transaction {} has {}/{} confirmations", tx_hash, depth, confirmations);
+        return Ok(TxState::Pending);
+    }
+
+    if !receipt.status() {
+        return Ok(TxState::Failed(format!("transaction {} reverted", tx_hash)));
+    }
+
+    // reading the log
     for log in receipt.logs() {
         // finding the event
-        if let Ok(event) =  <VC_added as SolEvent>::decode_log(&log.inner){
-            log::info!("VcAdded event:\n{:?}", event.reserialize());
-            return Ok(());
+        if E::decode_log(&log.inner).is_ok() {
+            log::info!("{} event found in transaction {}", E::SIGNATURE, tx_hash);
+            return Ok(TxState::Confirmed);
+        }
+    }
+    Ok(TxState::Failed(format!("no {} event found in the receipt", E::SIGNATURE)))
+}
+
+/// Polls the receipt of the given transaction until it is confirmed or the timeout elapses.
+/// Returns [`TxState::Pending`] on timeout.
+pub async fn wait_for_tx_event<E: SolEvent>(provider: &DynProvider, tx_hash: TxHash, confirmations: u64, timeout: Duration) -> Result<TxState, IssuerError> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let state = check_tx_event::<E>(provider, tx_hash, confirmations).await?;
+        if state != TxState::Pending || tokio::time::Instant::now() >= deadline {
+            return Ok(state);
         }
+        tokio::time::sleep(Duration::from_millis(500)).await;
     }
-    Err(IssuerError::OtherError("no VcAdded event found in the receipt".to_owned()))
+}
+
+/// Looks for the VC_added event of `credential_id` in the blocks mined since `since`, newest first,
+/// and returns the hash of the transaction that registered the credential
+pub async fn find_vc_added(identity_sc: &IdentityInstance<DynProvider>, credential_id: U256, since: SystemTime) -> Result<Option<TxHash>, IssuerError> {
+    let provider = identity_sc.provider();
+    let since = since.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().saturating_sub(CLOCK_DRIFT);
+    let mut to = provider.get_block_number().await
+        .map_err(|err| IssuerError::ContractError(format!("Block number request failed: {}", err)))?;
+    loop {
+        let from = to.saturating_sub(LOG_SEARCH_BATCH - 1);
+        let filter = Filter::new()
+            .address(*identity_sc.address())
+            .from_block(from)
+            .to_block(to)
+            .event(VC_added::SIGNATURE);
+        let logs = provider.get_logs(&filter).await
+            .map_err(|err| IssuerError::ContractError(format!("Logs request failed: {}", err)))?;
+        for log in &logs {
+            if VC_added::decode_log(&log.inner).is_ok_and(|event| event.data.vc_id == credential_id) {
+                return Ok(log.transaction_hash);
+            }
+        }
 
-}
\ No newline at end of file
+        let first_block = provider.get_block_by_number(BlockNumberOrTag::Number(from)).await
+            .map_err(|err| IssuerError::ContractError(format!("Block request failed: {}", err)))?;
+        if from == 0 || first_block.is_none_or(|block| block.header.timestamp < since) {
+            return Ok(None);
+        }
+        to = from - 1;
+    }
+}
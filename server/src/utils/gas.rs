@@ -0,0 +1,203 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use alloy::contract::{CallBuilder, CallDecoder};
+use alloy::providers::Provider;
+use clap::ValueEnum;
+
+use crate::errors::IssuerError;
+
+use super::configs::GasConfig;
+
+/// Fee model used to price the transactions sent to the smart contracts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GasStrategyKind {
+    /// Pre EIP-1559 transactions, priced with a single gas price
+    Legacy,
+    /// EIP-1559 transactions, priced with max fee and priority fee
+    Eip1559,
+}
+
+/// Gas pricing policy applied to every write towards the smart contracts.
+/// Built from the [`GasConfig`], falling back to per-network defaults based on the chain id.
+#[derive(Debug, Clone)]
+pub struct GasStrategy {
+    pub kind: GasStrategyKind,
+    /// Fixed gas price (legacy only), skips the estimation when set
+    pub gas_price: Option<u128>,
+    /// Multiplier applied to the estimated fees
+    pub fee_multiplier: f64,
+    /// Multiplier applied to the estimated gas limit
+    pub gas_limit_multiplier: f64,
+    /// Upper bound for the gas price (legacy) or the max fee per gas (EIP-1559)
+    pub max_fee_per_gas: Option<u128>,
+    /// Upper bound for the priority fee (EIP-1559 only)
+    pub max_priority_fee_per_gas: Option<u128>,
+}
+
+/// Chains served by IOTA/Shimmer EVM nodes (SEDIMARK chain included)
+const IOTA_EVM_CHAINS: [u64; 4] = [1072, 1073, 1074, 8822];
+/// Gas price historically used by the issuer on IOTA EVM chains
+const IOTA_EVM_GAS_PRICE: u128 = 10_000_000_000;
+
+impl GasStrategy {
+    /// Default policy for the given network
+    pub fn for_chain(chain_id: u64) -> Self {
+        if IOTA_EVM_CHAINS.contains(&chain_id) {
+            Self {
+                kind: GasStrategyKind::Legacy,
+                gas_price: Some(IOTA_EVM_GAS_PRICE),
+                fee_multiplier: 1.0,
+                gas_limit_multiplier: 1.2,
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+            }
+        } else {
+            Self {
+                kind: GasStrategyKind::Eip1559,
+                gas_price: None,
+                fee_multiplier: 1.25,
+                gas_limit_multiplier: 1.2,
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+            }
+        }
+    }
+
+    /// Network defaults overridden by the explicit configuration
+    pub fn new(config: &GasConfig, chain_id: u64) -> Self {
+        let defaults = Self::for_chain(chain_id);
+        let kind = config.gas_strategy.unwrap_or(defaults.kind);
+        // a fixed price only makes sense for the strategy it was meant for
+        let gas_price = match kind {
+            GasStrategyKind::Legacy => config.gas_price.or(defaults.gas_price),
+            GasStrategyKind::Eip1559 => None,
+        };
+
+        Self {
+            kind,
+            gas_price,
+            fee_multiplier: config.gas_fee_multiplier.unwrap_or(defaults.fee_multiplier),
+            gas_limit_multiplier: config.gas_limit_multiplier.unwrap_or(defaults.gas_limit_multiplier),
+            max_fee_per_gas: config.max_fee_per_gas.or(defaults.max_fee_per_gas),
+            max_priority_fee_per_gas: config.max_priority_fee_per_gas.or(defaults.max_priority_fee_per_gas),
+        }
+    }
+
+    /// Sets gas limit and fees of a contract call according to the strategy
+    pub async fn apply<P, D>(&self, call: CallBuilder<P, D>) -> Result<CallBuilder<P, D>, IssuerError>
+    where
+        P: Provider,
+        D: CallDecoder,
+    {
+        let gas_limit = call.estimate_gas().await
+            .map_err(|err| IssuerError::ContractError(format!("Gas estimation failed: {}", err)))?;
+        let call = call.gas(scale(gas_limit as u128, self.gas_limit_multiplier) as u64);
+
+        match self.kind {
+            GasStrategyKind::Legacy => {
+                let gas_price = match self.gas_price {
+                    Some(gas_price) => gas_price,
+                    None => {
+                        let estimated = call.provider.get_gas_price().await
+                            .map_err(|err| IssuerError::ContractError(format!("Gas price request failed: {}", err)))?;
+                        scale(estimated, self.fee_multiplier)
+                    }
+                };
+                let gas_price = cap(gas_price, self.max_fee_per_gas);
+                log::debug!("Legacy gas price: {}", gas_price);
+                Ok(call.gas_price(gas_price))
+            }
+            GasStrategyKind::Eip1559 => {
+                let estimation = call.provider.estimate_eip1559_fees().await
+                    .map_err(|err| IssuerError::ContractError(format!("Fee estimation failed: {}", err)))?;
+                let max_fee_per_gas = cap(scale(estimation.max_fee_per_gas, self.fee_multiplier), self.max_fee_per_gas);
+                let max_priority_fee_per_gas = cap(
+                    scale(estimation.max_priority_fee_per_gas, self.fee_multiplier),
+                    self.max_priority_fee_per_gas,
+                ).min(max_fee_per_gas);
+                log::debug!("EIP-1559 fees: max {} priority {}", max_fee_per_gas, max_priority_fee_per_gas);
+                Ok(call
+                    .max_fee_per_gas(max_fee_per_gas)
+                    .max_priority_fee_per_gas(max_priority_fee_per_gas))
+            }
+        }
+    }
+}
+
+fn scale(value: u128, multiplier: f64) -> u128 {
+    (value as f64 * multiplier).ceil() as u128
+}
+
+fn cap(value: u128, limit: Option<u128>) -> u128 {
+    limit.map_or(value, |limit| value.min(limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEDIMARK_CHAIN: u64 = 1074;
+    const SEPOLIA_CHAIN: u64 = 11155111;
+
+    fn config() -> GasConfig {
+        GasConfig {
+            gas_strategy: None,
+            gas_price: None,
+            gas_fee_multiplier: None,
+            gas_limit_multiplier: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+        }
+    }
+
+    #[test]
+    fn defaults_follow_the_chain() {
+        let iota = GasStrategy::new(&config(), SEDIMARK_CHAIN);
+        assert_eq!(iota.kind, GasStrategyKind::Legacy);
+        assert_eq!(iota.gas_price, Some(IOTA_EVM_GAS_PRICE));
+
+        let other = GasStrategy::new(&config(), SEPOLIA_CHAIN);
+        assert_eq!(other.kind, GasStrategyKind::Eip1559);
+        assert_eq!(other.gas_price, None);
+        assert_eq!(other.fee_multiplier, 1.25);
+    }
+
+    #[test]
+    fn configuration_overrides_the_defaults() {
+        let strategy = GasStrategy::new(&GasConfig {
+            gas_strategy: Some(GasStrategyKind::Eip1559),
+            gas_price: Some(1),
+            gas_fee_multiplier: Some(2.0),
+            gas_limit_multiplier: Some(1.5),
+            max_fee_per_gas: Some(100),
+            max_priority_fee_per_gas: Some(10),
+        }, SEDIMARK_CHAIN);
+        assert_eq!(strategy.kind, GasStrategyKind::Eip1559);
+        // a fixed price is meaningless for EIP-1559
+        assert_eq!(strategy.gas_price, None);
+        assert_eq!(strategy.fee_multiplier, 2.0);
+        assert_eq!(strategy.gas_limit_multiplier, 1.5);
+        assert_eq!(strategy.max_fee_per_gas, Some(100));
+        assert_eq!(strategy.max_priority_fee_per_gas, Some(10));
+
+        let legacy = GasStrategy::new(&GasConfig { gas_strategy: Some(GasStrategyKind::Legacy), gas_price: Some(7), ..config() }, SEPOLIA_CHAIN);
+        assert_eq!(legacy.gas_price, Some(7));
+    }
+
+    #[test]
+    fn scale_rounds_up() {
+        assert_eq!(scale(100, 1.0), 100);
+        assert_eq!(scale(100, 1.25), 125);
+        assert_eq!(scale(21_001, 1.2), 25_202);
+        assert_eq!(scale(0, 2.0), 0);
+    }
+
+    #[test]
+    fn cap_applies_the_limit_when_set() {
+        assert_eq!(cap(100, None), 100);
+        assert_eq!(cap(100, Some(50)), 50);
+        assert_eq!(cap(10, Some(50)), 10);
+    }
+}
@@ -5,7 +5,6 @@
 
 use alloy::primitives::Address;
 use anyhow::{Context, Result};
-use deadpool_postgres::Pool;
 use identity_iota::{iota::{IotaClientExt, IotaDID, IotaIdentityClientExt, NetworkName}, prelude::IotaDocument, storage::{JwkDocumentExt, JwkMemStore, Storage}, verification::{jws::JwsAlgorithm, MethodScope}
 };
 use identity_stronghold::StrongholdStorage;
@@ -17,7 +16,7 @@ use iota_sdk::{
         Client, Password,
     },
     crypto::keys::bip39::Mnemonic,
-    types::block::{address::Bech32Address, output::AliasOutput},
+    types::block::{address::Bech32Address, output::{AliasOutput, AliasOutputBuilder, RentStructure}},
 };
 use serde_json::{self};
 
@@ -34,15 +33,18 @@ use identity_iota::{
 use serde_json::json;
 
 use crate::dtos::identity_dtos::CredentialSubject;
+use crate::errors::IssuerError;
+use crate::repository::database::DbClient;
 use crate::repository::{models::IssuerIdentity, operations::IssuerIdentityExt};
 
 use super::configs::{ConfigSecret, DLTConfig, KeyStorageConfig};
+use super::issuers::CredentialTemplate;
 
 pub type MemStorage = Storage<StrongholdStorage, StrongholdStorage>;
 
+/// Contracts shared by every issuer, each issuer has its own Identity contract
 pub struct SCAddresses{
     pub factory: Address,
-    pub identity: Address,
     pub fresc: Address
 }
 
@@ -50,22 +52,17 @@ pub struct IotaState {
     pub client: Client,
     pub key_storage: MemStorage,
     pub stronghold_storage: StrongholdStorage,
-    pub issuer_identity: IssuerIdentity,
-    pub issuer_document: IotaDocument,
     pub faucet_url: String,
     pub addresses: SCAddresses
 }
 
 impl IotaState {
     pub async fn init(
-        db_pool: &Pool,
         dlt_configuration: DLTConfig,
         key_storage_config: KeyStorageConfig,
     ) -> Result<Self> {
         log::info!("Creating or recovering issuer state...");
 
-        let pg_client = &db_pool.get().await?;
-
         let client = Client::builder()
             .with_node(&dlt_configuration.node_url)?
             .finish()
@@ -81,50 +78,65 @@ impl IotaState {
 
         let faucet_url = dlt_configuration.faucet_api_endpoint;
 
-        // check if a did already exists
-        let (issuer_identity, issuer_document) = match pg_client.get_identity_did().await {
+        let addresses = SCAddresses{
+            factory: dlt_configuration.factory_sc_address,
+            fresc: dlt_configuration.fresc_sc_address};
+
+        let iota_state = IotaState {
+            client,
+            key_storage,
+            stronghold_storage: secret_manager,
+            faucet_url,
+            addresses
+        };
+        Ok(iota_state)
+    }
+
+    /// Resolves the DID of the named issuer, creating and publishing it on the first start
+    pub async fn load_or_create_identity(&self, db_client: &DbClient, name: &str) -> Result<(IssuerIdentity, IotaDocument)> {
+        match db_client.get_identity_did(name).await {
             Ok(identity) => {
-                let issuer_document = client.resolve_did(&IotaDID::parse(&identity.did)?).await?;
-                (identity, issuer_document)
+                let issuer_document = self.client.resolve_did(&IotaDID::parse(&identity.did)?).await?;
+                Ok((identity, issuer_document))
             }
-            Err(_) => {
-                log::info!("Creating new identity... ");
+            Err(IssuerError::RowNotFound) => {
+                log::info!("Creating new identity for issuer {}... ", name);
 
                 // create a did with a verification method
                 let (_, issuer_document, fragment) = create_did(
-                    &client,
-                    secret_manager.as_secret_manager(),
-                    &key_storage,
-                    &faucet_url,
+                    &self.client,
+                    self.stronghold_storage.as_secret_manager(),
+                    &self.key_storage,
+                    &self.faucet_url,
                 )
                 .await?;
                 // save the created identity
                 let new_issuer_identity = IssuerIdentity {
                     did: issuer_document.id().to_string(),
                     fragment,
+                    name: name.to_owned(),
                 };
-                pg_client
+                db_client
                     .insert_identity_issuer(&new_issuer_identity)
                     .await?;
-                (new_issuer_identity, issuer_document)
+                Ok((new_issuer_identity, issuer_document))
             }
-        };
-
-        let addresses = SCAddresses{
-            factory: dlt_configuration.factory_sc_address,
-            identity: dlt_configuration.identity_sc_address,
-            fresc: dlt_configuration.fresc_sc_address};
+            Err(err) => Err(err.into()),
+        }
+    }
 
-        let iota_state = IotaState {
-            client,
-            key_storage,
-            stronghold_storage: secret_manager,
-            issuer_identity,
-            issuer_document,
-            faucet_url,
-            addresses
-        };
-        Ok(iota_state)
+    /// Publishes a new version of an issuer DID document in its Alias Output,
+    /// the storage deposit follows the size of the document
+    pub async fn publish_document(&self, document: IotaDocument) -> Result<IotaDocument, IssuerError> {
+        let alias_output: AliasOutput = self.client.update_did_output(document).await?;
+        let rent_structure: RentStructure = self.client.get_rent_structure().await?;
+        let alias_output: AliasOutput = AliasOutputBuilder::from(&alias_output)
+            .with_minimum_storage_deposit(rent_structure)
+            .finish()
+            .map_err(|err| IssuerError::OtherError(format!("Alias output error: {}", err)))?;
+        Ok(self.client
+            .publish_did_output(self.stronghold_storage.as_secret_manager(), alias_output)
+            .await?)
     }
 }
 
@@ -292,6 +304,7 @@ pub async fn create_credential(
     vc_id: Url,
     storage_issuer: &MemStorage,
     fragment_issuer: &String,
+    template: &CredentialTemplate,
     credential_subject: CredentialSubject,
 ) -> Result<(Jwt, DecodedJwtCredential)> {
 
@@ -299,7 +312,7 @@ pub async fn create_credential(
     let subject = json!({
         "id": holder_document.id().to_string(),
         "schema:alternateName": credential_subject.alternate_name,
-        "schema:memberOf": "SEDIMARK marketplace"
+        "schema:memberOf": template.member_of
     });
 
     let subject = Subject::from_json_value(subject)?;
@@ -310,12 +323,12 @@ pub async fn create_credential(
     let credential: Credential = CredentialBuilder::default()
         .id(vc_id)
         .issuer(Url::parse(issuer_document.id().as_str())?)
-        .type_("MarketplaceCredential") // TODO: define a type somewhere else
+        .type_(template.credential_type.clone())
         .expiration_date(
             Timestamp::now_utc()
-                .checked_add(Duration::days(365))
-                .unwrap(),
-        ) // TODO: define this as a parameter
+                .checked_add(Duration::days(template.validity_days))
+                .ok_or(IssuerError::OtherError("Credential expiration out of range".to_owned()))?,
+        )
         .issuance_date(Timestamp::now_utc().checked_sub(Duration::days(1)).unwrap()) //TODO: this solved an error with the eth node time
         .subject(subject)
         .context(identity_iota::core::Context::Obj(schema))
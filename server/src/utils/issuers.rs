@@ -0,0 +1,262 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::{HashMap, HashSet};
+use std::future::{ready, Ready};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use alloy::primitives::Address;
+use alloy::providers::DynProvider;
+use identity_iota::iota::IotaDocument;
+use serde::Deserialize;
+
+use crate::contracts::Identity::{self, IdentityInstance};
+use crate::errors::IssuerError;
+use crate::repository::database::Database;
+use crate::repository::models::IssuerIdentity;
+use crate::repository::operations::IssuerIdentityExt;
+
+use super::configs::IssuerUrl;
+use super::iota::IotaState;
+use super::wallet_binding::{verifies_erc1271, WalletBindingVerifier, WalletSignatureScheme};
+
+/// Name of the issuer used when no ISSUERS_FILE is configured, owner of the identity created before the multi-tenant schema
+pub const DEFAULT_ISSUER: &str = "default";
+
+/// Accepted validity of the credentials, up to a century
+const VALIDITY_DAYS: std::ops::RangeInclusive<u32> = 1..=36500;
+
+/// First path segments of the api, an issuer cannot take their name
+const RESERVED_NAMES: &[&str] = &["admin", "addresses", "challenges", "credentials", "did-document", "issuance-jobs"];
+
+/// Content of the credentials signed by an issuer
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CredentialTemplate {
+    pub credential_type: String,
+    /// Value of schema:memberOf in the credential subject
+    pub member_of: String,
+    pub validity_days: u32,
+}
+
+impl Default for CredentialTemplate {
+    fn default() -> Self {
+        Self {
+            credential_type: "MarketplaceCredential".to_owned(),
+            member_of: "SEDIMARK marketplace".to_owned(),
+            validity_days: 365,
+        }
+    }
+}
+
+/// Entry of the ISSUERS_FILE
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuerDefinition {
+    pub name: String,
+    /// Identity contract of the issuer, the first issuer falls back to the configured one
+    pub identity_sc_address: Option<Address>,
+    #[serde(default)]
+    pub template: CredentialTemplate,
+}
+
+impl IssuerDefinition {
+    /// Issuers of the deployment, the first one is the default issuer.
+    /// Without a file a single issuer named [`DEFAULT_ISSUER`] is served.
+    pub fn load(path: Option<&Path>) -> Result<Vec<Self>, IssuerError> {
+        let Some(path) = path else {
+            return Ok(vec![Self { name: DEFAULT_ISSUER.to_owned(), identity_sc_address: None, template: CredentialTemplate::default() }]);
+        };
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| IssuerError::OtherError(format!("Cannot read {}: {}", path.display(), err)))?;
+        let definitions: Vec<Self> = serde_json::from_str(&content)
+            .map_err(|err| IssuerError::OtherError(format!("Invalid issuers file {}: {}", path.display(), err)))?;
+        if definitions.is_empty() {
+            return Err(IssuerError::OtherError(format!("No issuer defined in {}", path.display())));
+        }
+
+        let mut names = HashSet::new();
+        for definition in &definitions {
+            let valid = !definition.name.is_empty()
+                && definition.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+            if !valid || RESERVED_NAMES.contains(&definition.name.as_str()) {
+                return Err(IssuerError::OtherError(format!("Invalid issuer name {:?}: lowercase letters, digits and dashes only, not {}", definition.name, RESERVED_NAMES.join(", "))));
+            }
+            if !names.insert(definition.name.as_str()) {
+                return Err(IssuerError::OtherError(format!("Issuer {} defined twice", definition.name)));
+            }
+            if !VALIDITY_DAYS.contains(&definition.template.validity_days) {
+                return Err(IssuerError::OtherError(format!("Issuer {}: validityDays must be between {} and {}", definition.name, VALIDITY_DAYS.start(), VALIDITY_DAYS.end())));
+            }
+        }
+        Ok(definitions)
+    }
+
+    /// Identity contract of every issuer, VC ids are allocated per issuer so a contract cannot be shared
+    pub fn resolve_contracts(definitions: &[Self], default_address: Address) -> Result<Vec<(String, Address)>, IssuerError> {
+        let mut contracts = Vec::new();
+        for (index, definition) in definitions.iter().enumerate() {
+            let address = match definition.identity_sc_address {
+                Some(address) => address,
+                None if index == 0 => default_address,
+                None => return Err(IssuerError::OtherError(format!("Issuer {} has no identityScAddress", definition.name))),
+            };
+            if let Some((other, _)) = contracts.iter().find(|(_, used)| *used == address) {
+                return Err(IssuerError::OtherError(format!("Issuers {} and {} share the contract {}", other, definition.name, address)));
+            }
+            contracts.push((definition.name.clone(), address));
+        }
+        Ok(contracts)
+    }
+}
+
+/// Issuer identity served by the deployment
+pub struct Issuer {
+    pub name: String,
+    pub identity: IssuerIdentity,
+    /// Last published DID document
+    document: RwLock<IotaDocument>,
+    pub identity_sc: IdentityInstance<DynProvider>,
+    pub wallet_binding: WalletBindingVerifier,
+    pub template: CredentialTemplate,
+    /// Base url of the issuer api, ends with a slash
+    pub api_url: IssuerUrl,
+    /// Audience of the challenges handed out by the issuer
+    pub audience: String,
+}
+
+impl Issuer {
+    pub fn document(&self) -> IotaDocument {
+        self.document.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Replaces the cached document once a new version is published
+    pub fn set_document(&self, document: IotaDocument) {
+        *self.document.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = document;
+    }
+}
+
+/// Chain the Identity contracts of the issuers are deployed on
+pub struct IssuerChain {
+    pub provider: DynProvider,
+    pub chain_id: u64,
+    pub signature_scheme: WalletSignatureScheme,
+}
+
+/// Issuers of the deployment by name
+pub struct IssuerRegistry {
+    issuers: HashMap<String, Arc<Issuer>>,
+    default: String,
+}
+
+impl IssuerRegistry {
+    /// Loads the identity of every issuer, creating and publishing the missing DIDs.
+    /// The default issuer keeps the plain api url and audience, the others are served under `api/{name}/`.
+    pub async fn init(
+        db_pool: &Database,
+        iota_state: &IotaState,
+        definitions: Vec<IssuerDefinition>,
+        contracts: Vec<(String, Address)>,
+        chain: &IssuerChain,
+        issuer_url: &IssuerUrl,
+    ) -> anyhow::Result<Self> {
+        let db_client = db_pool.get().await?;
+        // the DID created before the multi-tenant schema is bound to the name, renaming the issuer would replace it
+        if !definitions.iter().any(|definition| definition.name == DEFAULT_ISSUER) {
+            match db_client.get_identity_did(DEFAULT_ISSUER).await {
+                Ok(identity) => anyhow::bail!(
+                    "ISSUERS_FILE has no issuer named \"{}\", the owner of {}: keep it as the first issuer so its DID is not replaced",
+                    DEFAULT_ISSUER, identity.did
+                ),
+                Err(IssuerError::RowNotFound) => {},
+                Err(err) => return Err(err.into()),
+            }
+        }
+        let default = definitions.first().map(|definition| definition.name.clone()).unwrap_or(DEFAULT_ISSUER.to_owned());
+
+        let mut issuers = HashMap::new();
+        for (definition, (_, address)) in definitions.into_iter().zip(contracts) {
+            let (identity, document) = iota_state.load_or_create_identity(&db_client, &definition.name).await?;
+            let (api_url, audience) = if definition.name == default {
+                (issuer_url.join("api/")?, issuer_url.to_string())
+            } else {
+                let api_url = issuer_url.join(format!("api/{}/", definition.name))?;
+                let audience = api_url.to_string();
+                (api_url, audience)
+            };
+            log::info!("Issuer {}: {} on contract {}", definition.name, identity.did, address);
+            let contract_wallets = verifies_erc1271(&chain.provider, address).await?;
+            if !contract_wallets {
+                log::info!("Issuer {}: the contract does not verify ERC-1271 signatures, smart contract wallets are rejected", definition.name);
+            }
+
+            issuers.insert(definition.name.clone(), Arc::new(Issuer {
+                identity,
+                document: RwLock::new(document),
+                identity_sc: Identity::new(address, chain.provider.clone()),
+                wallet_binding: WalletBindingVerifier::new(chain.signature_scheme, chain.chain_id, address, contract_wallets),
+                template: definition.template,
+                api_url,
+                audience,
+                name: definition.name,
+            }));
+        }
+        Ok(Self { issuers, default })
+    }
+
+    pub fn get(&self, name: &str) -> Result<Arc<Issuer>, IssuerError> {
+        self.issuers.get(name).cloned().ok_or(IssuerError::IssuerNotFound(name.to_owned()))
+    }
+
+    /// The named issuer, or the default one when no name is given
+    pub fn resolve(&self, name: Option<&str>) -> Result<Arc<Issuer>, IssuerError> {
+        self.get(name.unwrap_or(&self.default))
+    }
+
+    pub fn is_default(&self, issuer: &Issuer) -> bool {
+        issuer.name == self.default
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Issuer>> {
+        self.issuers.values()
+    }
+
+    /// True when the first segment after `/api/` names an issuer
+    pub fn is_issuer_path(&self, path: &str) -> bool {
+        path.strip_prefix("/api/")
+            .and_then(|rest| rest.split('/').next())
+            .is_some_and(|name| self.issuers.contains_key(name))
+    }
+}
+
+/// Issuer addressed by the request: the `{issuer}` path segment or the default issuer
+pub struct CurrentIssuer(pub Arc<Issuer>);
+
+impl CurrentIssuer {
+    pub fn from_request_parts(req: &HttpRequest) -> Result<Self, IssuerError> {
+        let registry = req.app_data::<web::Data<IssuerRegistry>>()
+            .ok_or(IssuerError::OtherError("Issuer registry not configured".to_owned()))?;
+        registry.resolve(req.match_info().get("issuer")).map(CurrentIssuer)
+    }
+}
+
+impl FromRequest for CurrentIssuer {
+    type Error = IssuerError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Self::from_request_parts(req))
+    }
+}
+
+impl Deref for CurrentIssuer {
+    type Target = Issuer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
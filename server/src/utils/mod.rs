@@ -4,4 +4,16 @@
 
 pub mod iota;
 pub mod eth;
-pub mod configs;
\ No newline at end of file
+pub mod configs;
+pub mod gas;
+pub mod outbox;
+pub mod ownership;
+pub mod diagnostics;
+pub mod deployment;
+pub mod wallet_binding;
+pub mod blockchain_account;
+pub mod rate_limit;
+pub mod issuers;
+pub mod did_document;
+pub mod domain_linkage;
+pub mod did_web;
\ No newline at end of file
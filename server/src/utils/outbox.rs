@@ -0,0 +1,49 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+use alloy::primitives::U256;
+
+use crate::contracts::Identity::VC_Revoked;
+use crate::errors::IssuerError;
+use crate::repository::database::DbClient;
+use crate::repository::models::{ContractOperation, JobStatus, OperationKind};
+use crate::repository::operations::ContractOperationsExt;
+use crate::utils::eth::{submit_revoke_vc, wait_for_tx_event, TxState};
+use crate::utils::gas::GasStrategy;
+use crate::utils::issuers::Issuer;
+
+/// Revokes a credential of `issuer` recording the operation in the outbox.
+/// If the transaction is not confirmed within `timeout` the operation is left to the reconciler
+/// and [`TxState::Pending`] (or [`TxState::Dropped`]) is returned.
+pub async fn revoke_vc(
+    db_client: &DbClient,
+    issuer: &Issuer,
+    credential_id: i64,
+    gas_strategy: &GasStrategy,
+    confirmations: u64,
+    timeout: Duration,
+) -> Result<TxState, IssuerError> {
+    let identity_sc = &issuer.identity_sc;
+    let operation = ContractOperation::new(OperationKind::RevokeVc, issuer.name.clone(), credential_id.to_string(), None);
+    let operation = db_client.insert_operation(&operation).await?;
+
+    let tx_hash = match submit_revoke_vc(identity_sc, U256::from(credential_id), gas_strategy).await {
+        Ok(tx_hash) => tx_hash,
+        Err(err) => {
+            db_client.update_operation(&operation.id, JobStatus::Failed, None, Some(err.to_string())).await?;
+            return Err(err);
+        }
+    };
+    db_client.update_operation(&operation.id, JobStatus::Submitted, Some(tx_hash.to_string()), None).await?;
+
+    let state = wait_for_tx_event::<VC_Revoked>(identity_sc.provider(), tx_hash, confirmations, timeout).await?;
+    match &state {
+        TxState::Confirmed => db_client.update_operation(&operation.id, JobStatus::Confirmed, Some(tx_hash.to_string()), None).await?,
+        TxState::Failed(reason) => db_client.update_operation(&operation.id, JobStatus::Failed, Some(tx_hash.to_string()), Some(reason.clone())).await?,
+        TxState::Pending | TxState::Dropped => log::warn!("Revocation of credential {} still pending, tx {}", credential_id, tx_hash),
+    }
+    Ok(state)
+}
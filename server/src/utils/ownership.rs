@@ -0,0 +1,123 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use alloy::primitives::Address;
+use alloy::providers::DynProvider;
+use alloy::signers::Signature;
+use uuid::Uuid;
+
+use crate::contracts::Identity::{IdentityInstance, OwnershipTransferred};
+use crate::errors::IssuerError;
+use crate::repository::database::DbClient;
+use crate::repository::models::{JobStatus, OwnershipTransfer};
+use crate::repository::operations::OwnershipTransfersExt;
+use crate::utils::configs::ConfirmationConfig;
+use crate::utils::eth::{send_call, wait_for_tx_event, TxState};
+use crate::utils::gas::GasStrategy;
+
+/// Returns the current owner of the Identity smart contract
+pub async fn get_owner(identity_sc: &IdentityInstance<DynProvider>) -> Result<Address, IssuerError> {
+    identity_sc.owner().call().await
+        .map_err(|err| IssuerError::ContractError(format!("Owner request failed: {}", err)))
+}
+
+/// Fails unless the issuer signer owns the Identity smart contract
+pub async fn ensure_signer_is_owner(identity_sc: &IdentityInstance<DynProvider>, signer_address: Address) -> Result<(), IssuerError> {
+    let owner = get_owner(identity_sc).await?;
+    if owner != signer_address {
+        return Err(IssuerError::OwnershipError(format!(
+            "the issuer signer {} does not own the Identity contract {} (owner is {})",
+            signer_address, identity_sc.address(), owner
+        )));
+    }
+    Ok(())
+}
+
+/// First step of an ownership transfer: records the new owner and the message it has to sign
+pub async fn propose_transfer(
+    db_client: &DbClient,
+    identity_sc: &IdentityInstance<DynProvider>,
+    new_owner: Address,
+) -> Result<OwnershipTransfer, IssuerError> {
+    let current_owner = get_owner(identity_sc).await?;
+    if new_owner == Address::ZERO || new_owner == current_owner {
+        return Err(IssuerError::OwnershipError(format!("invalid new owner {}", new_owner)));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = SystemTime::now();
+    let transfer = OwnershipTransfer {
+        challenge: format!(
+            "I accept the ownership of the Identity contract {} from {}. Transfer id: {}",
+            identity_sc.address(), current_owner, id
+        ),
+        id,
+        contract: identity_sc.address().to_string(),
+        current_owner: current_owner.to_string(),
+        new_owner: new_owner.to_string(),
+        status: JobStatus::Pending.as_str().to_owned(),
+        tx_hash: None,
+        error: None,
+        created_at: now,
+        updated_at: now,
+    };
+    let transfer = db_client.insert_ownership_transfer(&transfer).await?;
+    log::info!("Ownership transfer {} proposed to {}", transfer.id, transfer.new_owner);
+    Ok(transfer)
+}
+
+/// Second step of an ownership transfer: checks that the new owner signed the challenge
+/// (personal_sign) and calls transferOwnership, waiting for the configured confirmations
+pub async fn accept_transfer(
+    db_client: &DbClient,
+    identity_sc: &IdentityInstance<DynProvider>,
+    signature: &str,
+    gas_strategy: &GasStrategy,
+    confirmation_config: &ConfirmationConfig,
+    timeout: Duration,
+) -> Result<OwnershipTransfer, IssuerError> {
+    let contract = identity_sc.address().to_string();
+    let mut transfer = db_client.get_latest_ownership_transfer(&contract, JobStatus::Pending).await
+        .map_err(|_| IssuerError::OwnershipError("no proposed ownership transfer".to_owned()))?;
+    let new_owner = Address::from_str(&transfer.new_owner)
+        .map_err(|_| IssuerError::OtherError("Invalid new owner address".to_owned()))?;
+
+    let recovered = Signature::from_str(signature)?.recover_address_from_msg(&transfer.challenge)?;
+    if recovered != new_owner {
+        return Err(IssuerError::OwnershipError("the challenge was not signed by the new owner".to_owned()));
+    }
+
+    let call = gas_strategy.apply(identity_sc.transferOwnership(new_owner)).await?;
+    let tx_hash = *send_call(call).await
+        .map_err(|err| IssuerError::ContractError(format!("transferOwnership failed: {}", err)))?
+        .tx_hash();
+    log::info!("transferOwnership transaction sent: {}", tx_hash);
+    db_client.update_ownership_transfer(&transfer.id, JobStatus::Submitted, Some(tx_hash.to_string()), None).await?;
+
+    let state = wait_for_tx_event::<OwnershipTransferred>(
+        identity_sc.provider(),
+        tx_hash,
+        confirmation_config.confirmation_depth,
+        timeout
+    ).await?;
+    let status = match state {
+        TxState::Confirmed => JobStatus::Confirmed,
+        TxState::Failed(reason) => {
+            db_client.update_ownership_transfer(&transfer.id, JobStatus::Failed, Some(tx_hash.to_string()), Some(reason.clone())).await?;
+            return Err(IssuerError::ContractError(reason));
+        }
+        TxState::Pending | TxState::Dropped => {
+            log::warn!("Ownership transfer {} not confirmed yet, tx {}", transfer.id, tx_hash);
+            JobStatus::Submitted
+        }
+    };
+    db_client.update_ownership_transfer(&transfer.id, status, Some(tx_hash.to_string()), None).await?;
+
+    transfer.status = status.as_str().to_owned();
+    transfer.tx_hash = Some(tx_hash.to_string());
+    Ok(transfer)
+}
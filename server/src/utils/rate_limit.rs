@@ -0,0 +1,156 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::errors::IssuerError;
+
+/// Keys tracked before the idle ones are swept away
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// In-memory sliding window limiter, one window per key (e.g. a DID or an IP address)
+pub struct RateLimiter {
+    max_requests: usize,
+    window: Duration,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    /// A limit of 0 disables the limiter
+    pub fn new(max_requests: usize, window: Duration) -> Self {
+        Self { max_requests, window, hits: Mutex::new(HashMap::new()) }
+    }
+
+    /// Records a request for `key`, fails with the seconds to wait when the limit is reached
+    pub fn check(&self, key: &str) -> Result<(), IssuerError> {
+        if self.max_requests == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if hits.len() > SWEEP_THRESHOLD {
+            Self::retain_active(&mut hits, now, self.window);
+        }
+
+        let key_hits = hits.entry(key.to_owned()).or_default();
+        while key_hits.front().is_some_and(|first| now.duration_since(*first) >= self.window) {
+            key_hits.pop_front();
+        }
+        if key_hits.len() >= self.max_requests {
+            let retry_after = key_hits.front()
+                .map(|first| self.window.saturating_sub(now.duration_since(*first)))
+                .unwrap_or(self.window);
+            return Err(IssuerError::RateLimitExceeded(retry_after.as_secs().max(1)));
+        }
+        key_hits.push_back(now);
+        Ok(())
+    }
+
+    /// Forgets the keys without requests in the current window, returns how many were dropped
+    pub fn sweep(&self) -> u64 {
+        let mut hits = self.hits.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let before = hits.len();
+        Self::retain_active(&mut hits, Instant::now(), self.window);
+        (before - hits.len()) as u64
+    }
+
+    fn retain_active(hits: &mut HashMap<String, VecDeque<Instant>>, now: Instant, window: Duration) {
+        hits.retain(|_, key_hits| key_hits.back().is_some_and(|last| now.duration_since(*last) < window));
+    }
+}
+
+/// Limiters of the challenge endpoint
+pub struct ChallengeRateLimiters {
+    pub per_did: RateLimiter,
+    pub per_ip: RateLimiter,
+    /// Peers whose forwarded client address is trusted
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl ChallengeRateLimiters {
+    /// Key of the per IP limit: the client address forwarded by a trusted proxy, the peer address otherwise.
+    /// Forwarding headers sent by any other peer are ignored, they could be spoofed.
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded: Option<&str>) -> String {
+        let Some(peer) = peer else {
+            return String::new();
+        };
+        let forwarded = forwarded
+            .filter(|_| self.trusted_proxies.contains(&peer))
+            .and_then(|address| address.parse::<IpAddr>().ok()
+                .or_else(|| address.parse::<SocketAddr>().ok().map(|address| address.ip())));
+        forwarded.unwrap_or(peer).to_string()
+    }
+
+    /// Sweeps both limiters, returns the keys dropped
+    pub fn sweep(&self) -> u64 {
+        self.per_did.sweep() + self.per_ip.sweep()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_each_key_on_its_own() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.check("did:iota:a").is_ok());
+        assert!(limiter.check("did:iota:a").is_ok());
+        match limiter.check("did:iota:a") {
+            Err(IssuerError::RateLimitExceeded(retry_after)) => assert!((1..=60).contains(&retry_after)),
+            other => panic!("expected a rate limit error, got {:?}", other.err()),
+        }
+        assert!(limiter.check("did:iota:b").is_ok());
+    }
+
+    #[test]
+    fn zero_disables_the_limit() {
+        let limiter = RateLimiter::new(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            assert!(limiter.check("127.0.0.1").is_ok());
+        }
+    }
+
+    #[test]
+    fn window_slides() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(50));
+        assert!(limiter.check("127.0.0.1").is_ok());
+        assert!(limiter.check("127.0.0.1").is_err());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.check("127.0.0.1").is_ok());
+    }
+
+    #[test]
+    fn forwarded_address_only_from_trusted_proxies() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let limiters = ChallengeRateLimiters {
+            per_did: RateLimiter::new(0, Duration::from_secs(60)),
+            per_ip: RateLimiter::new(0, Duration::from_secs(60)),
+            trusted_proxies: vec![proxy],
+        };
+        assert_eq!(limiters.client_ip(Some(proxy), Some("203.0.113.7")), "203.0.113.7");
+        assert_eq!(limiters.client_ip(Some(proxy), Some("203.0.113.7:5123")), "203.0.113.7");
+        assert_eq!(limiters.client_ip(Some(proxy), Some("[2001:db8::1]:443")), "2001:db8::1");
+        assert_eq!(limiters.client_ip(Some(proxy), Some("unknown")), "10.0.0.1");
+        assert_eq!(limiters.client_ip(Some(proxy), None), "10.0.0.1");
+
+        let client: IpAddr = "198.51.100.2".parse().unwrap();
+        assert_eq!(limiters.client_ip(Some(client), Some("203.0.113.7")), "198.51.100.2");
+        assert_eq!(limiters.client_ip(None, Some("203.0.113.7")), "");
+    }
+
+    #[test]
+    fn sweep_drops_idle_keys() {
+        let limiter = RateLimiter::new(5, Duration::from_millis(50));
+        limiter.check("idle").unwrap();
+        std::thread::sleep(Duration::from_millis(60));
+        limiter.check("active").unwrap();
+        assert_eq!(limiter.sweep(), 1);
+        assert_eq!(limiter.sweep(), 0);
+    }
+}
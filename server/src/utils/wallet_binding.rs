@@ -0,0 +1,254 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::str::FromStr;
+
+use alloy::primitives::{eip191_hash_message, Address, Bytes, FixedBytes, B256, U256};
+use alloy::providers::{DynProvider, Provider};
+use alloy::signers::Signature;
+use alloy::sol;
+use alloy::sol_types::{eip712_domain, Eip712Domain, SolCall, SolStruct};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::errors::IssuerError;
+
+sol! {
+    /// Typed message signed by the holder wallet to bind it to its DID
+    #[derive(Debug)]
+    struct WalletBinding {
+        string holderDid;
+        string nonce;
+        uint256 expiry;
+    }
+
+    /// ERC-1271 signature validation of smart contract wallets (e.g. Safe)
+    #[sol(rpc)]
+    interface IERC1271 {
+        function isValidSignature(bytes32 hash, bytes signature) external view returns (bytes4 magicValue);
+    }
+
+    /// ERC-5267 domain of the contracts verifying EIP-712 signatures
+    #[sol(rpc)]
+    interface IERC5267 {
+        function eip712Domain() external view returns (bytes1 fields, string name, string version, uint256 chainId, address verifyingContract, bytes32 salt, uint256[] extensions);
+    }
+}
+
+/// Value returned by isValidSignature when the signature is valid
+const ERC1271_MAGIC_VALUE: FixedBytes<4> = FixedBytes([0x16, 0x26, 0xba, 0x7e]);
+
+/// How the holder wallet signs the issuance challenge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WalletSignatureScheme {
+    /// EIP-712 typed data (eth_signTypedData_v4) bound to the chain and to the Identity contract,
+    /// only for Identity contracts that verify EIP-712 signatures
+    Eip712,
+    /// personal_sign of the raw challenge, verified by the Identity contract
+    PersonalSign,
+}
+
+impl WalletSignatureScheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WalletSignatureScheme::Eip712 => "eip712",
+            WalletSignatureScheme::PersonalSign => "personal_sign",
+        }
+    }
+}
+
+impl FromStr for WalletSignatureScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eip712" => Ok(WalletSignatureScheme::Eip712),
+            "personal_sign" => Ok(WalletSignatureScheme::PersonalSign),
+            other => Err(format!("Unknown wallet signature scheme: {}", other)),
+        }
+    }
+}
+
+/// Verifies the signature binding a wallet to the DID of a holder
+#[derive(Debug, Clone)]
+pub struct WalletBindingVerifier {
+    pub scheme: WalletSignatureScheme,
+    /// Chain the holder accounts must belong to
+    pub chain_id: u64,
+    pub domain: Eip712Domain,
+    /// True when the Identity contract verifies ERC-1271 signatures, otherwise
+    /// the signatures of smart contract wallets are rejected
+    pub contract_wallets: bool,
+}
+
+/// Wallet that signed a binding, with the challenge handed to addUser
+#[derive(Debug, Clone)]
+pub struct VerifiedBinding {
+    pub address: Address,
+    /// True when the signature was accepted through ERC-1271
+    pub contract_wallet: bool,
+    /// raw challenge (personal_sign) or 0x-prefixed EIP-712 signing hash
+    pub contract_challenge: String,
+}
+
+impl WalletBindingVerifier {
+    /// The domain separates the signatures across networks and Identity contracts
+    pub fn new(scheme: WalletSignatureScheme, chain_id: u64, identity_address: Address, contract_wallets: bool) -> Self {
+        Self {
+            scheme,
+            chain_id,
+            contract_wallets,
+            domain: eip712_domain! {
+                name: "SEDIMARK Issuer",
+                version: "1",
+                chain_id: chain_id,
+                verifying_contract: identity_address,
+            },
+        }
+    }
+
+    /// Checks that the Identity contract verifies the signatures of the scheme. The Identity contract
+    /// recovers the personal_sign hash of the challenge, so EIP-712 needs a contract exposing the same
+    /// ERC-5267 domain as the issuer. Returns the reason of the mismatch.
+    pub async fn check_contract(&self, provider: &DynProvider) -> Result<(), String> {
+        if self.scheme != WalletSignatureScheme::Eip712 {
+            return Ok(());
+        }
+        let verifying_contract = self.domain.verifying_contract.unwrap_or_default();
+        let domain = IERC5267::new(verifying_contract, provider)
+            .eip712Domain()
+            .call()
+            .await
+            .map_err(|_| format!("{} does not verify EIP-712 signatures, use the personal-sign scheme", verifying_contract))?;
+
+        let expected = (
+            self.domain.name.as_deref().unwrap_or_default(),
+            self.domain.version.as_deref().unwrap_or_default(),
+            self.domain.chain_id.unwrap_or_default(),
+            verifying_contract,
+        );
+        if (domain.name.as_str(), domain.version.as_str(), domain.chainId, domain.verifyingContract) != expected {
+            return Err(format!(
+                "EIP-712 domain of {} is {} v{} on chain {}, the issuer signs for {} v{} on chain {}",
+                verifying_contract, domain.name, domain.version, domain.chainId, expected.0, expected.1, expected.2
+            ));
+        }
+        Ok(())
+    }
+
+    fn message(holder_did: &str, nonce: &str, expiry: i64) -> WalletBinding {
+        WalletBinding {
+            holderDid: holder_did.to_owned(),
+            nonce: nonce.to_owned(),
+            expiry: U256::from(expiry.max(0)),
+        }
+    }
+
+    /// Payload for eth_signTypedData_v4, None when the scheme is personal_sign
+    pub fn typed_data(&self, holder_did: &str, nonce: &str, expiry: i64) -> Option<Value> {
+        if self.scheme != WalletSignatureScheme::Eip712 {
+            return None;
+        }
+        Some(json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "version", "type": "string" },
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" }
+                ],
+                "WalletBinding": [
+                    { "name": "holderDid", "type": "string" },
+                    { "name": "nonce", "type": "string" },
+                    { "name": "expiry", "type": "uint256" }
+                ]
+            },
+            "primaryType": "WalletBinding",
+            "domain": self.domain,
+            "message": {
+                "holderDid": holder_did,
+                "nonce": nonce,
+                "expiry": expiry
+            }
+        }))
+    }
+
+    /// Hash signed by the wallet and challenge handed to addUser
+    fn signing_hash(&self, holder_did: &str, nonce: &str, expiry: i64) -> (B256, String) {
+        match self.scheme {
+            WalletSignatureScheme::Eip712 => {
+                let signing_hash = Self::message(holder_did, nonce, expiry).eip712_signing_hash(&self.domain);
+                (signing_hash, signing_hash.to_string())
+            }
+            WalletSignatureScheme::PersonalSign => (eip191_hash_message(nonce), nonce.to_owned()),
+        }
+    }
+
+    /// Checks that `wallet` signed the binding of `holder_did` to the challenge `nonce`.
+    /// ECDSA recovery is tried first, smart contract wallets are then asked through ERC-1271
+    /// when the Identity contract verifies their signatures too.
+    /// Returns None when the signature does not belong to the wallet.
+    pub async fn verify(
+        &self,
+        provider: &DynProvider,
+        wallet: Address,
+        signature: &Bytes,
+        holder_did: &str,
+        nonce: &str,
+        expiry: i64,
+    ) -> Result<Option<VerifiedBinding>, IssuerError> {
+        let (signing_hash, contract_challenge) = self.signing_hash(holder_did, nonce, expiry);
+
+        // contract wallets may use signatures of any length, e.g. several owners of a Safe
+        if let Ok(ecdsa) = Signature::try_from(signature.as_ref()) {
+            if ecdsa.recover_address_from_prehash(&signing_hash).ok() == Some(wallet) {
+                return Ok(Some(VerifiedBinding { address: wallet, contract_wallet: false, contract_challenge }));
+            }
+        }
+
+        let code = provider.get_code_at(wallet).await
+            .map_err(|err| IssuerError::ContractError(format!("Code request failed: {}", err)))?;
+        if code.is_empty() {
+            return Ok(None);
+        }
+        // addUser would revert after the credential is signed
+        if !self.contract_wallets {
+            log::warn!("Wallet {} is a smart contract, the Identity contract cannot verify its signature", wallet);
+            return Ok(None);
+        }
+        let magic_value = IERC1271::new(wallet, provider)
+            .isValidSignature(signing_hash, signature.clone())
+            .call()
+            .await;
+        match magic_value {
+            Ok(magic_value) if magic_value == ERC1271_MAGIC_VALUE => {
+                log::info!("Signature accepted by the contract wallet {} (ERC-1271)", wallet);
+                Ok(Some(VerifiedBinding { address: wallet, contract_wallet: true, contract_challenge }))
+            }
+            Ok(_) => Ok(None),
+            Err(err) => {
+                // reverts are the usual answer of wallets to invalid signatures
+                log::debug!("isValidSignature call on {} failed: {}", wallet, err);
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// True when the deployed code of `contract` calls isValidSignature, i.e. it verifies
+/// the ERC-1271 signatures of smart contract wallets besides the ECDSA ones
+pub async fn verifies_erc1271(provider: &DynProvider, contract: Address) -> Result<bool, IssuerError> {
+    let code = provider.get_code_at(contract).await
+        .map_err(|err| IssuerError::ContractError(format!("Code request failed: {}", err)))?;
+    Ok(code.windows(4).any(|window| window == IERC1271::isValidSignatureCall::SELECTOR))
+}
+
+/// Bytes passed as `_challenge` to addUser for a job signed with the given scheme
+pub fn contract_challenge_bytes(scheme: WalletSignatureScheme, challenge: &str) -> Result<Bytes, IssuerError> {
+    match scheme {
+        WalletSignatureScheme::Eip712 => Bytes::from_str(challenge)
+            .map_err(|_| IssuerError::OtherError("Invalid EIP-712 signing hash".to_owned())),
+        WalletSignatureScheme::PersonalSign => Ok(Bytes::from(challenge.as_bytes().to_vec())),
+    }
+}
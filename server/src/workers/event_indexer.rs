@@ -0,0 +1,169 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+use alloy::primitives::{Address, B256};
+use alloy::providers::{DynProvider, Provider};
+use alloy::rpc::types::{BlockNumberOrTag, Filter, Log};
+use alloy::sol_types::SolEvent;
+use serde_json::json;
+
+use crate::contracts::Identity::{OwnershipTransferred, VC_Revoked, VC_added};
+use crate::errors::IssuerError;
+use crate::repository::database::{Database, DbClient};
+use crate::repository::models::{ContractEvent, IndexerCursor};
+use crate::repository::operations::ContractEventsExt;
+use crate::utils::configs::IndexerConfig;
+
+/// Follows the logs of the Identity smart contract and stores them in the database.
+/// The last `indexer_reorg_depth` blocks are indexed again at every run. When the hash of the
+/// last indexed block is no longer canonical the indexer walks back to the last canonical block.
+pub async fn event_indexer(
+    pool: Database,
+    provider: DynProvider,
+    contract_address: Address,
+    config: IndexerConfig,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.indexer_poll_interval));
+    loop {
+        interval.tick().await;
+        if let Err(err) = index_events(&pool, &provider, contract_address, &config).await {
+            log::error!("Event indexer error on {}: {}", contract_address, err);
+        }
+    }
+}
+
+async fn index_events(
+    pool: &Database,
+    provider: &DynProvider,
+    contract_address: Address,
+    config: &IndexerConfig,
+) -> Result<(), IssuerError> {
+    let db_client = pool.get().await?;
+    let contract = contract_address.to_string();
+
+    let head = provider.get_block_number().await
+        .map_err(|err| IssuerError::ContractError(format!("Block number request failed: {}", err)))?;
+
+    let mut from = match db_client.get_indexer_cursor(&contract).await {
+        Ok(cursor) if is_canonical(provider, &cursor).await? => {
+            (cursor.block_number as u64 + 1).saturating_sub(config.indexer_reorg_depth).max(config.indexer_start_block)
+        }
+        Ok(cursor) => {
+            log::warn!("Reorg detected at block {}, looking for the last canonical indexed block", cursor.block_number);
+            match last_canonical_block(&db_client, provider, &cursor).await? {
+                Some(block_number) => (block_number + 1).max(config.indexer_start_block),
+                None => {
+                    log::error!(
+                        "ALERT: no block indexed for {} before {} is canonical, indexing again from block {}",
+                        contract, cursor.block_number, config.indexer_start_block
+                    );
+                    config.indexer_start_block
+                }
+            }
+        }
+        Err(IssuerError::RowNotFound) => config.indexer_start_block,
+        Err(err) => return Err(err),
+    };
+
+    while from <= head {
+        let to = (from + config.indexer_batch_size - 1).min(head);
+        let filter = Filter::new()
+            .address(contract_address)
+            .from_block(from)
+            .to_block(to)
+            .events([VC_added::SIGNATURE, VC_Revoked::SIGNATURE, OwnershipTransferred::SIGNATURE]);
+        let logs = provider.get_logs(&filter).await
+            .map_err(|err| IssuerError::ContractError(format!("Logs request failed: {}", err)))?;
+
+        // drop what was indexed on a possibly different fork
+        db_client.remove_events_from(&contract, from as i64).await?;
+        for event in logs.iter().filter_map(|log| to_contract_event(&contract, log)) {
+            log::debug!("Indexed {} at block {}", event.event, event.block_number);
+            db_client.insert_event(&event).await?;
+        }
+
+        let to_hash = block_hash(provider, to).await?
+            .ok_or(IssuerError::ContractError(format!("Block {} not found", to)))?;
+        db_client.set_indexer_cursor(&IndexerCursor {
+            contract: contract.clone(),
+            block_number: to as i64,
+            block_hash: to_hash.to_string(),
+        }).await?;
+        from = to + 1;
+    }
+    Ok(())
+}
+
+/// True when the block of the cursor is still part of the canonical chain
+async fn is_canonical(provider: &DynProvider, cursor: &IndexerCursor) -> Result<bool, IssuerError> {
+    let hash = block_hash(provider, cursor.block_number as u64).await?;
+    Ok(hash.is_some_and(|hash| hash.to_string() == cursor.block_hash))
+}
+
+/// Walks back the blocks of the stored events, newest first, until one is still canonical.
+/// The blocks before it are canonical as well, so indexing can resume right after it.
+async fn last_canonical_block(db_client: &DbClient, provider: &DynProvider, cursor: &IndexerCursor) -> Result<Option<u64>, IssuerError> {
+    let mut before = cursor.block_number;
+    loop {
+        let block = match db_client.get_event_block_before(&cursor.contract, before).await {
+            Ok(block) => block,
+            Err(IssuerError::RowNotFound) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        if is_canonical(provider, &block).await? {
+            return Ok(Some(block.block_number as u64));
+        }
+        log::warn!("Indexed block {} is no longer canonical", block.block_number);
+        before = block.block_number;
+    }
+}
+
+async fn block_hash(provider: &DynProvider, number: u64) -> Result<Option<B256>, IssuerError> {
+    let block = provider.get_block_by_number(BlockNumberOrTag::Number(number)).await
+        .map_err(|err| IssuerError::ContractError(format!("Block request failed: {}", err)))?;
+    Ok(block.map(|block| block.header.hash))
+}
+
+fn to_contract_event(contract: &str, log: &Log) -> Option<ContractEvent> {
+    let (event, vc_id, account, data) = match log.topic0()? {
+        topic if *topic == VC_added::SIGNATURE_HASH => {
+            let decoded = VC_added::decode_log(&log.inner).ok()?.data;
+            let data = json!({
+                "vcId": decoded.vc_id.to_string(),
+                "extracted": decoded.extracted.to_string(),
+                "expiration": decoded.expiration.to_string(),
+                "block": decoded.block.to_string(),
+            });
+            ("VC_added", Some(decoded.vc_id.to_string()), Some(decoded.extracted.to_string()), data)
+        }
+        topic if *topic == VC_Revoked::SIGNATURE_HASH => {
+            let decoded = VC_Revoked::decode_log(&log.inner).ok()?.data;
+            let data = json!({ "vcId": decoded.vc_id.to_string() });
+            ("VC_Revoked", Some(decoded.vc_id.to_string()), None, data)
+        }
+        topic if *topic == OwnershipTransferred::SIGNATURE_HASH => {
+            let decoded = OwnershipTransferred::decode_log(&log.inner).ok()?.data;
+            let data = json!({
+                "previousOwner": decoded.previousOwner.to_string(),
+                "newOwner": decoded.newOwner.to_string(),
+            });
+            ("OwnershipTransferred", None, Some(decoded.newOwner.to_string()), data)
+        }
+        _ => return None,
+    };
+
+    Some(ContractEvent {
+        contract: contract.to_owned(),
+        block_number: log.block_number? as i64,
+        block_hash: log.block_hash?.to_string(),
+        tx_hash: log.transaction_hash?.to_string(),
+        log_index: log.log_index? as i64,
+        event: event.to_owned(),
+        vc_id,
+        account,
+        data: data.to_string(),
+    })
+}
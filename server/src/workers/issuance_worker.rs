@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use actix_web::web;
+use alloy::primitives::{TxHash, U256};
+use alloy::providers::DynProvider;
+use tokio::sync::Notify;
+
+use crate::contracts::Identity::IdentityInstance;
+use crate::errors::IssuerError;
+use crate::repository::database::{Database, DbClient};
+use crate::repository::models::{ContractOperation, IssuanceJob, JobStatus, OperationKind};
+use crate::repository::operations::{ContractOperationsExt, IssuanceJobsExt, VcIdReservationsExt};
+use crate::utils::eth::{submit_add_user, UserRegistration};
+use crate::utils::gas::GasStrategy;
+use crate::utils::issuers::IssuerRegistry;
+use crate::utils::wallet_binding::{contract_challenge_bytes, WalletSignatureScheme};
+use crate::workers::reconciler::{find_on_chain, record_on_chain, OnChain};
+
+/// Wakes up the issuance worker as soon as a new job is stored
+#[derive(Default)]
+pub struct IssuanceQueue {
+    notify: Notify,
+}
+
+impl IssuanceQueue {
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+}
+
+/// Registers the issued credentials on the Identity smart contract of their issuer.
+/// Jobs are read from the database, so the ones left behind by a restart are resumed.
+pub async fn issuance_worker(
+    pool: Database,
+    issuers: web::Data<IssuerRegistry>,
+    gas_strategy: GasStrategy,
+    queue: web::Data<IssuanceQueue>,
+    poll_interval: Duration,
+) {
+    loop {
+        if let Err(err) = process_jobs(&pool, &issuers, &gas_strategy).await {
+            log::error!("Issuance worker error: {}", err);
+        }
+        tokio::select! {
+            _ = queue.notify.notified() => {},
+            _ = tokio::time::sleep(poll_interval) => {},
+        }
+    }
+}
+
+async fn process_jobs(
+    pool: &Database,
+    issuers: &IssuerRegistry,
+    gas_strategy: &GasStrategy,
+) -> Result<(), IssuerError> {
+    let db_client = pool.get().await?;
+
+    // Receipts of the submitted jobs are followed by the reconciler
+    for job in db_client.get_issuance_jobs_by_status(JobStatus::Pending).await? {
+        // jobs of an issuer removed from the configuration wait for it to come back
+        let issuer = match issuers.get(&job.issuer) {
+            Ok(issuer) => issuer,
+            Err(err) => {
+                log::warn!("Job {} skipped: {}", job.id, err);
+                continue;
+            }
+        };
+        let operation = match db_client.get_latest_operation_by_job(&job.id).await {
+            // an interrupted pass may have sent the transaction without recording it
+            Ok(operation) if operation.status == JobStatus::Pending.as_str() => {
+                match find_on_chain(&issuer.identity_sc, &operation).await {
+                    Ok(OnChain::Missing) => operation,
+                    Ok(on_chain) => {
+                        record_on_chain(&db_client, &operation, on_chain).await?;
+                        continue;
+                    }
+                    Err(err) => {
+                        log::warn!("Job {} skipped, cannot check the contract state: {}", job.id, err);
+                        continue;
+                    }
+                }
+            }
+            // the job was not updated after its operation was sent
+            Ok(operation) if operation.status != JobStatus::Failed.as_str() => {
+                let status = JobStatus::from_str(&operation.status).map_err(IssuerError::OtherError)?;
+                db_client.update_issuance_job(&job.id, status, operation.tx_hash, None).await?;
+                continue;
+            }
+            // record the intended operation before touching the chain
+            Ok(_) | Err(IssuerError::RowNotFound) => {
+                let operation = ContractOperation::new(OperationKind::AddUser, job.issuer.clone(), job.credential_id.clone(), Some(job.id.clone()));
+                db_client.insert_operation(&operation).await?
+            }
+            Err(err) => return Err(err),
+        };
+
+        match submit_job(&issuer.identity_sc, gas_strategy, &job).await {
+            Ok(tx_hash) => {
+                let tx_hash = Some(tx_hash.to_string());
+                db_client.update_operation(&operation.id, JobStatus::Submitted, tx_hash.clone(), None).await?;
+                db_client.update_issuance_job(&job.id, JobStatus::Submitted, tx_hash, None).await?;
+            }
+            Err(err) => {
+                log::error!("Job {} submission failed: {}", job.id, err);
+                // the transaction may have reached the chain before the error
+                match find_on_chain(&issuer.identity_sc, &operation).await {
+                    Ok(OnChain::Missing) => {
+                        db_client.update_operation(&operation.id, JobStatus::Failed, None, Some(err.to_string())).await?;
+                        fail_job(&db_client, &job.id, None, err.to_string()).await?;
+                    }
+                    Ok(on_chain) => record_on_chain(&db_client, &operation, on_chain).await?,
+                    Err(check_err) => log::warn!("Job {} left pending, cannot check the contract state: {}", job.id, check_err),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Marks a job as failed and gives its VC id back
+pub async fn fail_job(
+    db_client: &DbClient,
+    job_id: &str,
+    tx_hash: Option<String>,
+    reason: String,
+) -> Result<(), IssuerError> {
+    db_client.update_issuance_job(job_id, JobStatus::Failed, tx_hash, Some(reason)).await?;
+    db_client.release_vc_id(job_id).await
+}
+
+/// Sends the addUser transaction of a job
+pub async fn submit_job(
+    identity_sc: &IdentityInstance<DynProvider>,
+    gas_strategy: &GasStrategy,
+    job: &IssuanceJob,
+) -> Result<TxHash, IssuerError> {
+    let credential_id = U256::from_str(&job.credential_id)
+        .map_err(|_| IssuerError::OtherError("Invalid credential id".to_owned()))?;
+    let scheme = WalletSignatureScheme::from_str(&job.signature_scheme)
+        .map_err(IssuerError::OtherError)?;
+
+    let registration = UserRegistration {
+        credential_id,
+        expiration_date: U256::from(job.expiration_date),
+        issuance_date: U256::from(job.issuance_date),
+        challenge: contract_challenge_bytes(scheme, &job.challenge)?,
+        wallet_signature: job.wallet_signature.clone(),
+    };
+    submit_add_user(identity_sc, &registration, gas_strategy).await
+}
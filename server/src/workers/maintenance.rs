@@ -0,0 +1,159 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use actix_web::web;
+use alloy::primitives::U256;
+use tokio::time::MissedTickBehavior;
+
+use crate::repository::challenge_store::ChallengeStore;
+use crate::repository::database::Database;
+use crate::repository::operations::{ContractOperationsExt, HoldersChallengesExt, IssuanceJobsExt, OwnershipTransfersExt, VcIdReservationsExt};
+use crate::utils::configs::MaintenanceConfig;
+use crate::utils::issuers::IssuerRegistry;
+use crate::utils::rate_limit::ChallengeRateLimiters;
+
+const CHALLENGES_TASK: &str = "challenges";
+const CACHES_TASK: &str = "caches";
+const RETENTION_TASK: &str = "retention";
+const RESERVATIONS_TASK: &str = "reservations";
+
+/// Outcome of the runs of a maintenance task
+#[derive(Debug, Clone, Default)]
+pub struct TaskStats {
+    pub runs: u64,
+    pub failures: u64,
+    pub last_run: Option<SystemTime>,
+    pub last_duration: Duration,
+    /// Entries removed by the last successful run
+    pub last_removed: u64,
+    pub total_removed: u64,
+    /// Error of the last run, cleared by a successful one
+    pub last_error: Option<String>,
+}
+
+/// Stats of every maintenance task, shared with the admin endpoints
+#[derive(Default)]
+pub struct MaintenanceStats {
+    tasks: Mutex<BTreeMap<&'static str, TaskStats>>,
+}
+
+impl MaintenanceStats {
+    pub fn snapshot(&self) -> BTreeMap<&'static str, TaskStats> {
+        self.tasks.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    fn record(&self, task: &'static str, started: Instant, outcome: &Result<u64, anyhow::Error>) {
+        let mut tasks = self.tasks.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let stats = tasks.entry(task).or_default();
+        stats.runs += 1;
+        stats.last_run = Some(SystemTime::now());
+        stats.last_duration = started.elapsed();
+        match outcome {
+            Ok(removed) => {
+                stats.last_removed = *removed;
+                stats.total_removed += removed;
+                stats.last_error = None;
+            }
+            Err(err) => {
+                stats.failures += 1;
+                stats.last_error = Some(err.to_string());
+            }
+        }
+    }
+}
+
+/// Removes the expired state: holders challenges, idle rate limiter windows, the VC id reservations
+/// taken over by the contracts and, past their retention, the completed jobs, contract operations and ownership transfers.
+/// Every task runs on its own interval and takes a fresh connection at every run,
+/// so a broken connection only fails the run it was used by.
+pub async fn maintenance_scheduler(
+    pool: Database,
+    issuers: web::Data<IssuerRegistry>,
+    challenge_store: web::Data<ChallengeStore>,
+    rate_limiters: web::Data<ChallengeRateLimiters>,
+    stats: web::Data<MaintenanceStats>,
+    config: MaintenanceConfig,
+) {
+    let (pool, issuers, challenge_store, rate_limiters) = (&pool, &issuers, &challenge_store, &rate_limiters);
+    tokio::join!(
+        run_every(CHALLENGES_TASK, config.challenge_cleanup_interval, &stats, move || async move {
+            challenge_store.cleanup_challenges().await
+        }),
+        run_every(CACHES_TASK, config.cache_cleanup_interval, &stats, move || async move {
+            Ok(rate_limiters.sweep())
+        }),
+        run_every(RETENTION_TASK, config.retention_cleanup_interval, &stats, move || async move {
+            prune_expired(pool, config).await
+        }),
+        run_every(RESERVATIONS_TASK, config.reservation_cleanup_interval, &stats, move || async move {
+            prune_reservations(pool, issuers).await
+        }),
+    );
+}
+
+async fn run_every<F, Fut>(task: &'static str, interval_secs: u64, stats: &MaintenanceStats, run: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<u64, anyhow::Error>>,
+{
+    if interval_secs == 0 {
+        log::info!("Maintenance task {} disabled", task);
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    // a slow run postpones the next one instead of causing a burst
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let started = Instant::now();
+        let outcome = run().await;
+        match &outcome {
+            Ok(removed) => log::info!("Maintenance task {} completed, {} entries removed in {}ms", task, removed, started.elapsed().as_millis()),
+            Err(err) => log::error!("Maintenance task {} error: {}", task, err),
+        }
+        stats.record(task, started, &outcome);
+    }
+}
+
+/// Deletes the completed records older than their retention, returns how many were removed
+async fn prune_expired(pool: &Database, config: MaintenanceConfig) -> Result<u64, anyhow::Error> {
+    let db_client = pool.get().await?;
+    let mut removed = 0;
+    if let Some(before) = retention_cutoff(config.job_retention_days) {
+        removed += db_client.prune_issuance_jobs(before).await?;
+    }
+    if let Some(before) = retention_cutoff(config.audit_retention_days) {
+        removed += db_client.prune_operations(before).await?;
+        removed += db_client.prune_ownership_transfers(before).await?;
+    }
+    Ok(removed)
+}
+
+/// Deletes the reservations of the ids below the first free id of each contract,
+/// they are never candidates again. Returns how many were removed.
+async fn prune_reservations(pool: &Database, issuers: &IssuerRegistry) -> Result<u64, anyhow::Error> {
+    let db_client = pool.get().await?;
+    let mut removed = 0;
+    for issuer in issuers.iter() {
+        let first_free_id: U256 = issuer.identity_sc.getFreeVCid().call().await
+            .map_err(|err| anyhow::anyhow!("VC ID request failed for issuer {}: {}", issuer.name, err))?;
+        let first_free_id = i64::try_from(first_free_id)
+            .map_err(|_| anyhow::anyhow!("VC ID of issuer {} out of range", issuer.name))?;
+        removed += db_client.prune_vc_id_reservations(&issuer.name, first_free_id).await?;
+    }
+    Ok(removed)
+}
+
+/// None when the retention is disabled
+fn retention_cutoff(days: u64) -> Option<SystemTime> {
+    if days == 0 {
+        return None;
+    }
+    SystemTime::now().checked_sub(Duration::from_secs(days * 24 * 3600))
+}
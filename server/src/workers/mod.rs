@@ -0,0 +1,8 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+pub mod issuance_worker;
+pub mod reconciler;
+pub mod event_indexer;
+pub mod maintenance;
\ No newline at end of file
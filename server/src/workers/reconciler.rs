@@ -0,0 +1,262 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use actix_web::web;
+use alloy::primitives::{TxHash, U256};
+use alloy::providers::DynProvider;
+
+use crate::contracts::Identity::{IdentityInstance, VC_Revoked, VC_added};
+use crate::errors::IssuerError;
+use crate::repository::database::{Database, DbClient};
+use crate::repository::models::{ContractOperation, JobStatus, OperationKind};
+use crate::repository::operations::{ContractOperationsExt, IssuanceJobsExt};
+use crate::utils::configs::ConfirmationConfig;
+use crate::utils::eth::{check_tx_event, find_vc_added, submit_revoke_vc, TxState};
+use crate::utils::gas::GasStrategy;
+use crate::utils::issuers::IssuerRegistry;
+use crate::workers::issuance_worker::{fail_job, submit_job};
+
+/// Brings the outbox of contract operations in line with the chain.
+/// The first run happens at startup, so operations interrupted by a restart are resolved.
+/// Operations become confirmed only after `confirmation_depth` blocks, dropped transactions are sent again.
+/// Before an operation is sent again or failed the contract state is checked, so a credential
+/// registered by an unrecorded or earlier transaction keeps its VC id.
+pub async fn reconciler(
+    pool: Database,
+    issuers: web::Data<IssuerRegistry>,
+    gas_strategy: GasStrategy,
+    confirmation_config: ConfirmationConfig,
+    interval: Duration,
+    alert_after: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        match reconcile(&pool, &issuers, &gas_strategy, confirmation_config, alert_after).await {
+            Ok(_) => log::debug!("Outbox reconciliation completed"),
+            Err(err) => log::error!("Outbox reconciliation error: {}", err),
+        }
+    }
+}
+
+async fn reconcile(
+    pool: &Database,
+    issuers: &IssuerRegistry,
+    gas_strategy: &GasStrategy,
+    confirmation_config: ConfirmationConfig,
+    alert_after: Duration,
+) -> Result<(), IssuerError> {
+    let db_client = pool.get().await?;
+
+    for operation in db_client.get_operations_by_status(JobStatus::Submitted).await? {
+        let issuer = match issuers.get(&operation.issuer) {
+            Ok(issuer) => issuer,
+            Err(err) => {
+                log::warn!("Operation {} skipped: {}", operation.id, err);
+                continue;
+            }
+        };
+        let identity_sc = &issuer.identity_sc;
+        let state = match check_operation(identity_sc, &operation, confirmation_config.confirmation_depth).await {
+            Ok(state) => state,
+            Err(err) => {
+                log::warn!("Cannot check operation {}: {}", operation.id, err);
+                TxState::Pending
+            }
+        };
+        let state = match state {
+            TxState::Dropped | TxState::Failed(_) => match find_on_chain(identity_sc, &operation).await {
+                Ok(OnChain::Missing) => state,
+                Ok(on_chain) => {
+                    record_on_chain(&db_client, &operation, on_chain).await?;
+                    continue;
+                }
+                Err(err) => {
+                    log::warn!("Cannot check the contract state of operation {}: {}", operation.id, err);
+                    TxState::Pending
+                }
+            },
+            state => state,
+        };
+
+        match state {
+            TxState::Confirmed => {
+                log::info!("Operation {} ({}) confirmed", operation.id, operation.kind);
+                db_client.update_operation(&operation.id, JobStatus::Confirmed, operation.tx_hash.clone(), None).await?;
+                if let Some(job_id) = &operation.job_id {
+                    db_client.update_issuance_job(job_id, JobStatus::Confirmed, operation.tx_hash.clone(), None).await?;
+                }
+            }
+            TxState::Failed(reason) => {
+                log::error!("Operation {} ({}) failed: {}", operation.id, operation.kind, reason);
+                db_client.update_operation(&operation.id, JobStatus::Failed, operation.tx_hash.clone(), Some(reason.clone())).await?;
+                if let Some(job_id) = &operation.job_id {
+                    fail_job(&db_client, job_id, operation.tx_hash.clone(), reason).await?;
+                }
+            }
+            TxState::Dropped if operation.attempts >= confirmation_config.max_resubmissions => {
+                let reason = format!("transaction dropped after {} resubmissions", operation.attempts);
+                log::error!("Operation {} ({}) failed: {}", operation.id, operation.kind, reason);
+                db_client.update_operation(&operation.id, JobStatus::Failed, operation.tx_hash.clone(), Some(reason.clone())).await?;
+                if let Some(job_id) = &operation.job_id {
+                    fail_job(&db_client, job_id, operation.tx_hash.clone(), reason).await?;
+                }
+            }
+            TxState::Dropped => {
+                log::warn!("Transaction {:?} of operation {} dropped, sending it again", operation.tx_hash, operation.id);
+                match resubmit_operation(&db_client, identity_sc, gas_strategy, &operation).await {
+                    Ok(tx_hash) => {
+                        let tx_hash = tx_hash.to_string();
+                        db_client.resubmit_operation(&operation.id, &tx_hash).await?;
+                        if let Some(job_id) = &operation.job_id {
+                            db_client.update_issuance_job(job_id, JobStatus::Submitted, Some(tx_hash), None).await?;
+                        }
+                    }
+                    Err(err) => log::error!("Resubmission of operation {} failed: {}", operation.id, err),
+                }
+            }
+            TxState::Pending => alert_if_stale(&db_client, &operation, alert_after).await?,
+        }
+    }
+
+    // intended operations whose transaction was never recorded as sent
+    for operation in db_client.get_operations_by_status(JobStatus::Pending).await? {
+        let issuer = match issuers.get(&operation.issuer) {
+            Ok(issuer) => issuer,
+            Err(err) => {
+                log::warn!("Operation {} skipped: {}", operation.id, err);
+                continue;
+            }
+        };
+        match find_on_chain(&issuer.identity_sc, &operation).await {
+            Ok(OnChain::Missing) => alert_if_stale(&db_client, &operation, alert_after).await?,
+            Ok(on_chain) => record_on_chain(&db_client, &operation, on_chain).await?,
+            Err(err) => {
+                log::warn!("Cannot check the contract state of operation {}: {}", operation.id, err);
+                alert_if_stale(&db_client, &operation, alert_after).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Effect of an operation found in the contract state
+pub enum OnChain {
+    /// Neither registered nor revoked, the operation can be sent again or failed
+    Missing,
+    /// Credential registered by the given transaction
+    Registered(TxHash),
+    /// Credential revoked
+    Revoked,
+}
+
+/// Checks the contract state for the effect of an operation whose own transaction
+/// cannot be followed: never recorded, dropped or reverted
+pub async fn find_on_chain(
+    identity_sc: &IdentityInstance<DynProvider>,
+    operation: &ContractOperation,
+) -> Result<OnChain, IssuerError> {
+    let kind = OperationKind::from_str(&operation.kind).map_err(IssuerError::OtherError)?;
+    let credential_id = U256::from_str(&operation.credential_id)
+        .map_err(|_| IssuerError::OtherError("Invalid credential id".to_owned()))?;
+
+    match kind {
+        OperationKind::AddUser => Ok(find_vc_added(identity_sc, credential_id, operation.created_at).await?
+            .map_or(OnChain::Missing, OnChain::Registered)),
+        OperationKind::RevokeVc => {
+            let revoked = identity_sc.isRevoked(credential_id).call().await
+                .map_err(|err| IssuerError::ContractError(format!("isRevoked call failed: {}", err)))?;
+            Ok(if revoked { OnChain::Revoked } else { OnChain::Missing })
+        }
+    }
+}
+
+/// Records the effect found by [`find_on_chain`]: the registering transaction is followed
+/// like a submitted one, a revocation is confirmed right away
+pub async fn record_on_chain(
+    db_client: &DbClient,
+    operation: &ContractOperation,
+    on_chain: OnChain,
+) -> Result<(), IssuerError> {
+    match on_chain {
+        OnChain::Missing => Ok(()),
+        OnChain::Registered(tx_hash) => {
+            log::warn!("Credential {} of operation {} registered by transaction {}", operation.credential_id, operation.id, tx_hash);
+            let tx_hash = Some(tx_hash.to_string());
+            db_client.update_operation(&operation.id, JobStatus::Submitted, tx_hash.clone(), None).await?;
+            if let Some(job_id) = &operation.job_id {
+                db_client.update_issuance_job(job_id, JobStatus::Submitted, tx_hash, None).await?;
+            }
+            Ok(())
+        }
+        OnChain::Revoked => {
+            log::info!("Credential {} of operation {} already revoked", operation.credential_id, operation.id);
+            db_client.update_operation(&operation.id, JobStatus::Confirmed, operation.tx_hash.clone(), None).await
+        }
+    }
+}
+
+/// Checks the receipt of the operation
+async fn check_operation(
+    identity_sc: &IdentityInstance<DynProvider>,
+    operation: &ContractOperation,
+    confirmations: u64,
+) -> Result<TxState, IssuerError> {
+    let kind = OperationKind::from_str(&operation.kind).map_err(IssuerError::OtherError)?;
+    let tx_hash = operation.tx_hash.as_deref()
+        .and_then(|tx_hash| TxHash::from_str(tx_hash).ok())
+        .ok_or(IssuerError::OtherError(format!("Operation {} has no valid tx hash", operation.id)))?;
+
+    match kind {
+        OperationKind::AddUser => check_tx_event::<VC_added>(identity_sc.provider(), tx_hash, confirmations).await,
+        OperationKind::RevokeVc => check_tx_event::<VC_Revoked>(identity_sc.provider(), tx_hash, confirmations).await,
+    }
+}
+
+/// Sends again the transaction of an operation that was dropped
+async fn resubmit_operation(
+    db_client: &DbClient,
+    identity_sc: &IdentityInstance<DynProvider>,
+    gas_strategy: &GasStrategy,
+    operation: &ContractOperation,
+) -> Result<TxHash, IssuerError> {
+    let kind = OperationKind::from_str(&operation.kind).map_err(IssuerError::OtherError)?;
+    match kind {
+        OperationKind::AddUser => {
+            let job_id = operation.job_id.as_ref()
+                .ok_or(IssuerError::OtherError(format!("Operation {} has no issuance job", operation.id)))?;
+            let job = db_client.get_issuance_job(job_id).await?;
+            submit_job(identity_sc, gas_strategy, &job).await
+        }
+        OperationKind::RevokeVc => {
+            let credential_id = U256::from_str(&operation.credential_id)
+                .map_err(|_| IssuerError::OtherError("Invalid credential id".to_owned()))?;
+            submit_revoke_vc(identity_sc, credential_id, gas_strategy).await
+        }
+    }
+}
+
+/// Raises an alert for operations still unconfirmed after `alert_after`, once per operation
+async fn alert_if_stale(
+    db_client: &DbClient,
+    operation: &ContractOperation,
+    alert_after: Duration,
+) -> Result<(), IssuerError> {
+    if operation.alerted {
+        return Ok(());
+    }
+    let age = SystemTime::now().duration_since(operation.created_at).unwrap_or_default().as_secs();
+    if age < alert_after.as_secs() {
+        return Ok(());
+    }
+
+    log::error!(
+        "ALERT: contract operation {} ({} for credential {}) not confirmed after {}s, status {}, tx {:?}",
+        operation.id, operation.kind, operation.credential_id, age, operation.status, operation.tx_hash
+    );
+    db_client.mark_operation_alerted(&operation.id).await
+}